@@ -7,6 +7,7 @@ pub mod atlas;
 pub mod font;
 pub mod image;
 pub mod instance;
+pub mod world;
 use crate::assets;
 
 #[repr(C)]
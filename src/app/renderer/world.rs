@@ -0,0 +1,425 @@
+use super::Renderer;
+use crate::{
+    catmull_rom, Chain, Constraint, ForceField, Link, Node, SlotMap, Terrain, Vec2, World,
+};
+
+/// Draws the simulation with the app renderer, kept out of `World` so the physics builds headless
+pub trait WorldRenderer {
+    const NODE_COLOR: [u8; 3] = [240, 200, 64];
+
+    fn render_node(&self, node: &Node, color: [u8; 3], gfx: &mut Renderer);
+    /// Tints by stress when given, otherwise by the relative velocity of the endpoints
+    fn render_link(
+        &self,
+        link: &Link,
+        stress: Option<f32>,
+        nodes: &SlotMap<Node>,
+        gfx: &mut Renderer,
+    );
+    fn render_links(&self, links: &SlotMap<Link>, nodes: &SlotMap<Node>, gfx: &mut Renderer);
+    fn render_nodes(&self, nodes: &SlotMap<Node>, gfx: &mut Renderer);
+    fn render_structure(&self, links: &SlotMap<Link>, nodes: &SlotMap<Node>, gfx: &mut Renderer);
+    fn render_terrain(&self, terrain: &Terrain, gfx: &mut Renderer);
+    fn render_field(&self, field: &ForceField, gfx: &mut Renderer);
+    /// Draws the rail a constraint keeps its node on, unlimited rails are cut off,
+    /// angles, pulleys and gears ring their pivot, wheel or hubs
+    fn render_constraint(&self, constraint: &Constraint, gfx: &mut Renderer);
+    /// Draws a chain as one smooth rope through its nodes
+    fn render_chain(&self, chain: &Chain, gfx: &mut Renderer);
+    fn render(&self, gfx: &mut Renderer);
+    fn render_stress(&self, gfx: &mut Renderer);
+}
+
+// Ring peeking out around a node, marking the pivots of constraints
+fn ring(world: &World, p: Vec2, color: [u8; 3], gfx: &mut Renderer) {
+    let (old_col, old_stroke_col) = (gfx.color, gfx.stroke_color);
+    let old_stroke_width = gfx.stroke_width;
+    gfx.stroke_color = [
+        (gfx.color[0] as f32 * color[0] as f32 / 255.0) as u8,
+        (gfx.color[1] as f32 * color[1] as f32 / 255.0) as u8,
+        (gfx.color[2] as f32 * color[2] as f32 / 255.0) as u8,
+        gfx.color[3],
+    ];
+    gfx.color[3] = 0;
+    gfx.stroke_width = 0.08 / world.radius.sqrt();
+    let Vec2 { x, y } = p * world.scale();
+    gfx.circle(x, y, world.radius * 1.3);
+    (gfx.color, gfx.stroke_color) = (old_col, old_stroke_col);
+    gfx.stroke_width = old_stroke_width;
+}
+
+impl WorldRenderer for World {
+    fn render_node(&self, node: &Node, mut color: [u8; 3], gfx: &mut Renderer) {
+        let old_col = gfx.color;
+        let old_stroke_col = gfx.stroke_color;
+        let old_stroke_width = gfx.stroke_width;
+        let Vec2 { x, y } = node.p;
+
+        if node.mass > 1.0 {
+            color = [150, 150, 170];
+        }
+        if node.fixed_x() || node.fixed_y() {
+            color = [64, 180, 255];
+        }
+        if let Some(motor) = node.motor {
+            color = if motor.enabled {
+                [180, 255, 64]
+            } else {
+                [110, 140, 80]
+            };
+        }
+
+        gfx.color = [
+            (gfx.color[0] as f32 * color[0] as f32 / 255.0) as u8,
+            (gfx.color[1] as f32 * color[1] as f32 / 255.0) as u8,
+            (gfx.color[2] as f32 * color[2] as f32 / 255.0) as u8,
+            gfx.color[3],
+        ];
+        gfx.stroke_color = [
+            (gfx.color[0] as f32 * 0.7) as u8,
+            (gfx.color[1] as f32 * 0.7) as u8,
+            (gfx.color[2] as f32 * 0.7) as u8,
+            gfx.color[3],
+        ];
+        gfx.stroke_width = 0.08 / self.radius.sqrt();
+        gfx.circle(x * self.scale(), y * self.scale(), self.radius);
+
+        gfx.color = gfx.stroke_color;
+        if node.fixed() {
+            gfx.circle(x * self.scale(), y * self.scale(), self.radius * 0.45);
+        } else if node.fixed_x() {
+            gfx.line(
+                x * self.scale(),
+                y * self.scale() - self.radius * 0.8,
+                x * self.scale(),
+                y * self.scale() + self.radius * 0.8,
+                self.radius * 0.2,
+            );
+        } else if node.fixed_y() {
+            gfx.line(
+                x * self.scale() - self.radius * 0.8,
+                y * self.scale(),
+                x * self.scale() + self.radius * 0.8,
+                y * self.scale(),
+                self.radius * 0.2,
+            );
+        }
+
+        gfx.color = old_col;
+        gfx.stroke_color = old_stroke_col;
+        gfx.stroke_width = old_stroke_width;
+    }
+
+    fn render_link(
+        &self,
+        link: &Link,
+        stress: Option<f32>,
+        nodes: &SlotMap<Node>,
+        gfx: &mut Renderer,
+    ) {
+        let old_col = gfx.color;
+        let old_stroke_col = gfx.stroke_color;
+        let old_stroke_width = gfx.stroke_width;
+
+        // Links of removed nodes linger until the next flush
+        let (Some(a), Some(b)) = (nodes.get(link.n1()), nodes.get(link.n2())) else {
+            return;
+        };
+
+        let to_a = (a.p - b.p).norm();
+        let d = if let Some(stress) = stress {
+            // Red in tension, blue in compression, saturated at the breaking point
+            -stress.clamp(-1.0, 1.0)
+        } else {
+            let d = 4.0 * ((-to_a).dot(&b.v) + to_a.dot(&a.v));
+            d.abs().sqrt() * d.signum()
+        };
+
+        let rr = 1.0 - d.max(0.0);
+        let gg = rr + d.min(0.0);
+        let bb = 1.0 + d.min(0.0);
+
+        let color = [
+            (gfx.color[0] as f32 * rr) as u8,
+            (gfx.color[1] as f32 * gg) as u8,
+            (gfx.color[2] as f32 * bb) as u8,
+            gfx.color[3],
+        ];
+        gfx.color = color;
+
+        gfx.stroke_color = [color[0] / 2, color[1] / 2, color[2] / 2, color[3]];
+        gfx.stroke_width = 0.5;
+
+        match link {
+            Link::Link { .. } => {
+                gfx.line(
+                    a.p.x * self.scale(),
+                    a.p.y * self.scale(),
+                    b.p.x * self.scale(),
+                    b.p.y * self.scale(),
+                    self.link_width(),
+                );
+            }
+            Link::Hydraulic { .. } => {
+                gfx.line(
+                    a.p.x * self.scale(),
+                    a.p.y * self.scale(),
+                    b.p.x * self.scale(),
+                    b.p.y * self.scale(),
+                    self.link_width(),
+                );
+                gfx.color = [color[0] / 2, color[1] / 2, color[2] / 2, color[3]];
+                let to_b = b.p - (b.p - a.p) * 0.5;
+                gfx.line(
+                    a.p.x * self.scale(),
+                    a.p.y * self.scale(),
+                    to_b.x * self.scale(),
+                    to_b.y * self.scale(),
+                    self.link_width(),
+                );
+            }
+            Link::Spring { stiffness, .. } => {
+                let c = (-to_a).rot90() * self.link_width() / self.scale();
+                // Stiffer springs are wound tighter
+                let windings = (link.dist() * stiffness.sqrt().clamp(4.0, 32.0) * 3.0) as u32;
+                let inv = 1.0 / windings as f32;
+                let to_b = b.p - a.p;
+                gfx.stroke_width = 0.7;
+                for i in 0..windings {
+                    let d = i as f32 * inv;
+                    let p1 = (a.p - c + to_b * d) * self.scale();
+                    let p2 = (a.p + c + to_b * (d + inv)) * self.scale();
+                    gfx.line(p1.x, p1.y, p2.x, p2.y, self.link_width() * 0.25);
+                    let p2 = p2 - to_b * inv * self.scale();
+                    gfx.line(p1.x, p1.y, p2.x, p2.y, self.link_width() * 0.25);
+                }
+            }
+            Link::Rope { .. } => {
+                gfx.line(
+                    a.p.x * self.scale(),
+                    a.p.y * self.scale(),
+                    b.p.x * self.scale(),
+                    b.p.y * self.scale(),
+                    self.link_width() * 0.7,
+                );
+
+                gfx.color = [color[0] / 2, color[1] / 2, color[2] / 2, color[3]];
+                let to_b = b.p - a.p;
+                let c = to_b.norm().rot90() * self.link_width() / self.scale() * 0.45;
+                let windings = (link.dist() * 32.0) as u32;
+                let inv = 1.0 / windings as f32;
+                for i in 0..windings {
+                    let d = i as f32 * inv;
+                    let p1 = (a.p - c + to_b * d) * self.scale();
+                    let p2 = (a.p + c + to_b * (d + inv * 0.5)) * self.scale();
+                    gfx.line(p1.x, p1.y, p2.x, p2.y, self.link_width() * 0.25);
+                }
+            }
+        }
+        gfx.color = old_col;
+        gfx.stroke_color = old_stroke_col;
+        gfx.stroke_width = old_stroke_width;
+    }
+
+    fn render_links(&self, links: &SlotMap<Link>, nodes: &SlotMap<Node>, gfx: &mut Renderer) {
+        for l in links.values() {
+            self.render_link(l, None, nodes, gfx);
+        }
+    }
+
+    fn render_nodes(&self, nodes: &SlotMap<Node>, gfx: &mut Renderer) {
+        for n in nodes.values() {
+            self.render_node(n, Self::NODE_COLOR, gfx);
+        }
+    }
+
+    fn render_structure(&self, links: &SlotMap<Link>, nodes: &SlotMap<Node>, gfx: &mut Renderer) {
+        self.render_links(links, nodes, gfx);
+        self.render_nodes(nodes, gfx);
+    }
+
+    fn render_terrain(&self, terrain: &Terrain, gfx: &mut Renderer) {
+        let old_col = gfx.color;
+        gfx.color = [
+            (gfx.color[0] as f32 * 0.45) as u8,
+            (gfx.color[1] as f32 * 0.4) as u8,
+            (gfx.color[2] as f32 * 0.35) as u8,
+            gfx.color[3],
+        ];
+        for (a, b) in terrain.shape.edges() {
+            gfx.line(
+                a.x * self.scale(),
+                a.y * self.scale(),
+                b.x * self.scale(),
+                b.y * self.scale(),
+                self.link_width(),
+            );
+        }
+        gfx.color = old_col;
+    }
+
+    fn render_field(&self, field: &ForceField, gfx: &mut Renderer) {
+        let old_col = gfx.color;
+        let old_stroke_col = gfx.stroke_color;
+        let old_stroke_width = gfx.stroke_width;
+        let color = match field {
+            ForceField::Uniform { .. } => [160, 220, 255],
+            ForceField::Attractor { .. } => [255, 160, 220],
+            ForceField::Vortex { .. } => [200, 160, 255],
+        };
+        let Vec2 { x, y } = field.center() * self.scale();
+        if field.radius() != ForceField::UNBOUNDED {
+            gfx.color = [color[0], color[1], color[2], 24];
+            gfx.stroke_color = [color[0], color[1], color[2], 64];
+            gfx.stroke_width = 0.02;
+            gfx.circle(x, y, field.radius() * self.scale());
+        }
+        gfx.color = [color[0], color[1], color[2], 255];
+        gfx.stroke_color = [color[0] / 2, color[1] / 2, color[2] / 2, 255];
+        gfx.stroke_width = 0.3;
+        gfx.circle(x, y, self.radius * 0.6);
+        gfx.color = old_col;
+        gfx.stroke_color = old_stroke_col;
+        gfx.stroke_width = old_stroke_width;
+    }
+
+    fn render_constraint(&self, constraint: &Constraint, gfx: &mut Renderer) {
+        const RAIL_SPAN: f32 = 4.0;
+        let (origin, dir, span) = match *constraint {
+            Constraint::Track { origin, dir, .. } => (origin, dir, (-RAIL_SPAN, RAIL_SPAN)),
+            Constraint::Angle { node, .. } => {
+                if let Some(node) = self.nodes.get(node) {
+                    ring(self, node.p, [230, 128, 76], gfx);
+                }
+                return;
+            }
+            Constraint::Pulley { node, a, b, .. } => {
+                let Some(wheel) = self.nodes.get(node) else {
+                    return;
+                };
+                for end in [a, b] {
+                    let Some(end_node) = self.nodes.get(end) else {
+                        continue;
+                    };
+                    let rope = Link::Rope {
+                        n1: node,
+                        n2: end,
+                        dist: wheel.p.dist(&end_node.p),
+                        strength: Link::UNBREAKABLE,
+                    };
+                    self.render_link(&rope, None, &self.nodes, gfx);
+                }
+                ring(self, wheel.p, [160, 130, 100], gfx);
+                return;
+            }
+            Constraint::Gear { node, other, .. } => {
+                let (Some(hub), Some(other)) = (self.nodes.get(node), self.nodes.get(other)) else {
+                    return;
+                };
+                let old_col = gfx.color;
+                gfx.color[3] /= 2;
+                let (from, to) = (hub.p * self.scale(), other.p * self.scale());
+                gfx.line(from.x, from.y, to.x, to.y, self.link_width() * 0.3);
+                gfx.color = old_col;
+                ring(self, hub.p, [200, 200, 220], gfx);
+                ring(self, other.p, [200, 200, 220], gfx);
+                return;
+            }
+            Constraint::Slider { a, b, .. } => {
+                let (Some(a), Some(b)) = (self.nodes.get(a), self.nodes.get(b)) else {
+                    return;
+                };
+                let ab = b.p - a.p;
+                if ab.len2() == 0.0 {
+                    return;
+                }
+                (a.p, ab.norm(), (0.0, ab.len()))
+            }
+        };
+        let (min, max) = constraint.limits();
+        let (min, max) = (
+            if min.is_finite() { min } else { span.0 },
+            if max.is_finite() { max } else { span.1 },
+        );
+        let (from, to) = (
+            (origin + dir * min) * self.scale(),
+            (origin + dir * max) * self.scale(),
+        );
+        let old_col = gfx.color;
+        gfx.color = [
+            (gfx.color[0] as f32 * 0.6) as u8,
+            (gfx.color[1] as f32 * 0.6) as u8,
+            (gfx.color[2] as f32 * 0.65) as u8,
+            gfx.color[3],
+        ];
+        gfx.line(from.x, from.y, to.x, to.y, self.link_width() * 0.4);
+        gfx.color = old_col;
+    }
+
+    fn render_chain(&self, chain: &Chain, gfx: &mut Renderer) {
+        let points: Vec<Vec2> = chain
+            .nodes
+            .iter()
+            .filter_map(|&n| self.nodes.get(n))
+            .map(|n| n.p)
+            .collect();
+        let old_col = gfx.color;
+        gfx.color = [
+            (gfx.color[0] as f32 * 0.8) as u8,
+            (gfx.color[1] as f32 * 0.65) as u8,
+            (gfx.color[2] as f32 * 0.45) as u8,
+            gfx.color[3],
+        ];
+        for pair in catmull_rom(&points, 4).windows(2) {
+            let (from, to) = (pair[0] * self.scale(), pair[1] * self.scale());
+            gfx.line(from.x, from.y, to.x, to.y, self.link_width() * 0.7);
+        }
+        gfx.color = old_col;
+    }
+
+    fn render(&self, gfx: &mut Renderer) {
+        for f in self.fields.values() {
+            self.render_field(f, gfx);
+        }
+        for t in self.terrain.values() {
+            self.render_terrain(t, gfx);
+        }
+        for c in self.constraints.values() {
+            self.render_constraint(c, gfx);
+        }
+        // Chains hide their segments behind the curve
+        for c in self.chains.values() {
+            self.render_chain(c, gfx);
+        }
+        for (id, l) in self.links.iter() {
+            if !self.link_chains.contains_key(&id) {
+                self.render_link(l, None, &self.nodes, gfx);
+            }
+        }
+        for (id, n) in self.nodes.iter() {
+            if !self.node_chains.contains_key(&id) {
+                self.render_node(n, Self::NODE_COLOR, gfx);
+            }
+        }
+    }
+
+    fn render_stress(&self, gfx: &mut Renderer) {
+        for f in self.fields.values() {
+            self.render_field(f, gfx);
+        }
+        for t in self.terrain.values() {
+            self.render_terrain(t, gfx);
+        }
+        for c in self.constraints.values() {
+            self.render_constraint(c, gfx);
+        }
+        for (id, l) in self.links.iter() {
+            self.render_link(l, Some(self.link_stress(id)), &self.nodes, gfx);
+        }
+        for (id, n) in self.nodes.iter() {
+            if !self.node_chains.contains_key(&id) {
+                self.render_node(n, Self::NODE_COLOR, gfx);
+            }
+        }
+    }
+}
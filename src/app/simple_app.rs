@@ -1,10 +1,17 @@
 use super::renderer;
+use super::renderer::world::WorldRenderer;
 use super::App;
-use crate::Node;
-use crate::{integrator::*, Axes, Cooldown, Link, Vec2, World};
+use crate::{
+    integrator::*, Axes, Constraint, Cooldown, ForceField, Link, Shape, Terrain, Vec2, World,
+};
+use crate::{
+    AdaptiveSubsteps, ChainId, HydraulicControl, Layers, Motor, Node, NodeId, SlotMap, Solver,
+    SolverOrder, Wave, Xpbd,
+};
 use owned_ttf_parser::name::Name;
 use rand::Rng;
 use std::fs::File;
+use std::path::PathBuf;
 use std::rc::Rc;
 use winit::event::{MouseButton, WindowEvent};
 use winit::keyboard::KeyCode;
@@ -15,23 +22,43 @@ use winit::window::Window;
 enum Material {
     Node,
     Fixed,
-    Rotor,
+    Motor,
     Hydraulic,
     Spring,
     Roller,
     Rope,
+    Weight,
+    Pulley,
+    Gear,
 }
-const MATERIAL_LEN: u32 = Material::Rope as u32 + 1;
+const MATERIAL_LEN: u32 = Material::Gear as u32 + 1;
+const LINK_STRENGTHS: [f32; 4] = [Link::UNBREAKABLE, 400.0, 200.0, 100.0];
+const WEIGHT_MASS: f32 = 8.0;
+const FIELD_RADIUS: f32 = 1.0;
+// Stiffness and damping of new springs, medium, soft and stiff for unit masses
+const SPRING_PRESETS: [(f32, f32); 3] = [(200.0, 4.0), (50.0, 1.0), (1000.0, 10.0)];
+const LINEAR_DRAGS: [f32; 3] = [0.0, 0.2, 1.0];
+// Stroke of new hydraulics as fractions of their built length
+const HYDRAULIC_STROKE: (f32, f32) = (0.5, 1.5);
+const HYDRAULIC_PERIOD: f32 = 2.0;
+// Factor motor speed and torque change by per key press
+const MOTOR_STEP: f32 = 1.25;
+// Rest length of chained ropes relative to the distance they span
+const CHAIN_SLACK: f32 = 1.1;
+const XPBD_ITERATIONS: [u32; 4] = [4, 8, 16, 32];
 
 impl From<u32> for Material {
     fn from(value: u32) -> Self {
         match value {
             1 => Material::Fixed,
-            2 => Material::Rotor,
+            2 => Material::Motor,
             3 => Material::Hydraulic,
             4 => Material::Spring,
             5 => Material::Roller,
             6 => Material::Rope,
+            7 => Material::Weight,
+            8 => Material::Pulley,
+            9 => Material::Gear,
             _ => Material::Node,
         }
     }
@@ -40,31 +67,37 @@ impl From<u32> for Material {
 pub struct SimpleApp {
     pub app: *const App,
     world: World,
-    integrator: Euler,
-    selected_node: Option<u32>,
-    selected_nodes: Vec<u32>,
+    selected_node: Option<NodeId>,
+    selected_nodes: Vec<NodeId>,
     selection_start: Option<Vec2>,
     selected_material: Material,
     time_scale: f32,
     physics_cooldown: Cooldown,
     move_start_pos: Vec2,
     scale: f32,
+    link_strength: f32,
+    broken_links: u32,
+    show_stress: bool,
+    terrain_start: Option<Vec2>,
+    track_node: Option<NodeId>,
+    weld: bool,
+    pulley_rope: Option<(NodeId, NodeId)>,
+    chain_ropes: bool,
+    spring_preset: usize,
 }
 
 impl SimpleApp {
     pub fn new(app: &App) -> Self {
-        let world;
-        let file = File::open("assets/save.dat");
-        if let Ok(mut file) = file {
-            world = World::deserialize(&mut file).expect("Failed to load save file");
+        let save_path = Self::save_path();
+        let world = if save_path.exists() {
+            World::load(&save_path).expect("Failed to load save file")
         } else {
-            world = World::default();
-        }
+            World::default()
+        };
 
         Self {
             app,
             world,
-            integrator: Default::default(),
             selected_node: None,
             selected_nodes: Vec::new(),
             selection_start: None,
@@ -73,106 +106,259 @@ impl SimpleApp {
             physics_cooldown: Cooldown::new(std::time::Duration::from_secs_f32(1.0 / 256.0)),
             move_start_pos: Vec2::ZERO,
             scale: 1.0,
+            link_strength: Link::UNBREAKABLE,
+            broken_links: 0,
+            show_stress: false,
+            terrain_start: None,
+            track_node: None,
+            weld: false,
+            pulley_rope: None,
+            chain_ropes: false,
+            spring_preset: 0,
         }
     }
 
+    // First command line argument overrides the save file, .json saves are text scenes
+    fn save_path() -> PathBuf {
+        std::env::args()
+            .nth(1)
+            .map_or_else(|| PathBuf::from("assets/save.dat"), PathBuf::from)
+    }
+
     fn material_node(material: Material, x: f32, y: f32) -> Node {
-        match material {
+        let mut node = match material {
             Material::Node | Material::Hydraulic | Material::Spring | Material::Rope => {
                 Node::new(x, y)
             }
-            Material::Fixed => Node::new_fixed(x, y),
-            Material::Rotor => Node::new_rotor(x, y, 1.0),
+            Material::Fixed | Material::Pulley | Material::Gear => Node::new_fixed(x, y),
+            Material::Motor => Node::new_motor(x, y, Motor::default()),
             Material::Roller => Node::new_fixed_y(x, y),
+            Material::Weight => Node::new_mass(x, y, WEIGHT_MASS),
+        };
+        (node.restitution, node.friction) = Self::material_surface(material);
+        node
+    }
+
+    // Restitution and friction of placed nodes, rotors grip so wheels can drive
+    const fn material_surface(material: Material) -> (f32, f32) {
+        match material {
+            Material::Motor => (0.2, 1.0),
+            Material::Weight => (0.1, 0.6),
+            _ => (0.4, 0.5),
         }
     }
 
-    fn material_link(material: Material, dist: f32) -> Link {
+    fn material_link(material: Material, n1: NodeId, n2: NodeId, dist: f32) -> Link {
         match material {
-            Material::Rope { .. } => Link::Rope { n1: 0, n2: 1, dist },
+            Material::Rope { .. } | Material::Pulley { .. } => Link::Rope {
+                n1,
+                n2,
+                dist,
+                strength: Link::UNBREAKABLE,
+            },
             Material::Hydraulic { .. } => Link::Hydraulic {
-                n1: 0,
-                n2: 1,
+                n1,
+                n2,
                 dist,
                 speed: 1.0,
+                strength: Link::UNBREAKABLE,
+                min: 0.0,
+                max: f32::INFINITY,
+                control: HydraulicControl::Hold,
             },
             Material::Spring { .. } => Link::Spring {
-                n1: 0,
-                n2: 1,
+                n1,
+                n2,
                 dist,
-                stiffness: 1.0,
+                stiffness: SPRING_PRESETS[0].0,
+                damping: SPRING_PRESETS[0].1,
+                strength: Link::UNBREAKABLE,
+            },
+            _ => Link::Link {
+                n1,
+                n2,
+                dist,
+                strength: Link::UNBREAKABLE,
             },
-            _ => Link::Link { n1: 0, n2: 1, dist },
         }
     }
 
     const fn material_color(material: Material) -> [u8; 3] {
         match material {
-            Material::Rope { .. } => [160, 130, 100],
+            Material::Rope { .. } | Material::Pulley { .. } => [160, 130, 100],
             Material::Hydraulic { .. } => [32, 72, 180],
             Material::Spring { .. } => [255, 255, 128],
             _ => World::NODE_COLOR,
         }
     }
 
-    fn add_node(&mut self, x: f32, y: f32) -> u32 {
+    fn add_node(&mut self, x: f32, y: f32) -> NodeId {
         self.world
             .add(Self::material_node(self.selected_material, x, y))
     }
 
-    fn link_nodes(&mut self, node1: u32, node2: u32) {
+    fn link_nodes(&mut self, node1: NodeId, node2: NodeId) {
         match self.selected_material {
-            Material::Node | Material::Fixed | Material::Rotor | Material::Roller => {
-                self.world.link_node(Link::Link {
-                    n1: node1,
-                    n2: node2,
-                    dist: 0.0,
-                });
+            _ if node1 == node2 => return,
+            Material::Pulley => return self.string_pulley(node1, node2),
+            Material::Gear => return self.mesh_gears(node1, node2),
+            Material::Rope if self.chain_ropes => return self.string_chain(node1, node2),
+            _ => {}
+        }
+        let mut link = Self::material_link(self.selected_material, node1, node2, 0.0);
+        link.set_strength(self.link_strength);
+        if let Link::Spring {
+            stiffness, damping, ..
+        } = &mut link
+        {
+            (*stiffness, *damping) = SPRING_PRESETS[self.spring_preset];
+        }
+        if let Link::Hydraulic { min, max, .. } = &mut link {
+            let dist = self.world.nodes[node1].p.dist(&self.world.nodes[node2].p);
+            (*min, *max) = (dist * HYDRAULIC_STROKE.0, dist * HYDRAULIC_STROKE.1);
+        }
+        // In weld mode the new link keeps its angle to a link already at either end
+        let neighbours = [node1, node2].map(|n| {
+            self.world
+                .node_links
+                .get(&n)
+                .and_then(|links| links.first().copied())
+        });
+        let Some(link_id) = self.world.link_node(link) else {
+            return;
+        };
+        if self.weld {
+            for other in neighbours.into_iter().flatten() {
+                self.world.join_links(other, link_id, Constraint::RIGID);
             }
-            Material::Hydraulic => {
-                self.world.link_node(Link::Hydraulic {
-                    n1: node1,
-                    n2: node2,
-                    dist: 0.0,
-                    speed: 1.0,
+        }
+    }
+
+    // Chained ropes get a segment per node width so nothing slips between the segment nodes
+    fn string_chain(&mut self, a: NodeId, b: NodeId) {
+        let length = self.world.nodes[a].p.dist(&self.world.nodes[b].p) * CHAIN_SLACK;
+        let width = self.world.radius / self.world.scale() * 2.0;
+        let segments = (length / width).ceil().max(2.0) as u32;
+        self.world.add_chain(a, b, segments, length);
+    }
+
+    // The first rope of a pulley runs from a load to the wheel, the second from the wheel to the other load
+    fn string_pulley(&mut self, from: NodeId, to: NodeId) {
+        let nodes = &self.world.nodes;
+        match self.pulley_rope.take() {
+            Some((a, wheel)) if wheel == from && a != to && nodes.contains(a) => {
+                let length = nodes[a].p.dist(&nodes[wheel].p) + nodes[to].p.dist(&nodes[wheel].p);
+                self.world.add_constraint(Constraint::Pulley {
+                    node: wheel,
+                    a,
+                    b: to,
+                    length,
+                    ratio: 1.0,
                 });
             }
-            Material::Spring => {
-                self.world.link_node(Link::Spring {
-                    n1: node1,
-                    n2: node2,
-                    dist: 0.0,
-                    stiffness: 1.0,
-                });
+            _ => self.pulley_rope = Some((from, to)),
+        }
+    }
+
+    // Gears turn at the inverse ratio of their sizes, the mean length of the links at their hubs
+    fn mesh_gears(&mut self, hub: NodeId, other: NodeId) {
+        let size = |n: NodeId| {
+            let links = self.world.node_links.get(&n)?;
+            let total: f32 = links
+                .iter()
+                .map(|&l| self.world.link_length(&self.world.links[l]))
+                .sum();
+            Some(total / links.len() as f32)
+        };
+        let ratio = match (size(hub), size(other)) {
+            (Some(a), Some(b)) if b > 0.0 => a / b,
+            _ => 1.0,
+        };
+        self.world.add_constraint(Constraint::Gear {
+            node: hub,
+            other,
+            ratio,
+        });
+    }
+
+    // Changes the control of hydraulics touching the selection, or of every hydraulic without one
+    fn drive_hydraulics(&mut self, drive: impl Fn(HydraulicControl) -> HydraulicControl) {
+        for link in self.world.links.values_mut() {
+            let Link::Hydraulic {
+                n1, n2, control, ..
+            } = *link
+            else {
+                continue;
+            };
+            if self.selected_nodes.is_empty()
+                || self.selected_nodes.contains(&n1)
+                || self.selected_nodes.contains(&n2)
+            {
+                link.set_hydraulic_control(drive(control));
             }
-            Material::Rope => {
-                self.world.link_node(Link::Rope {
-                    n1: node1,
-                    n2: node2,
-                    dist: 0.0,
-                });
+        }
+    }
+
+    // Adjusts motors in the selection, or every motor without one
+    fn drive_motors(&mut self, drive: impl Fn(&mut Motor)) {
+        for (id, node) in self.world.nodes.iter_mut() {
+            if self.selected_nodes.is_empty() || self.selected_nodes.contains(&id) {
+                if let Some(motor) = &mut node.motor {
+                    drive(motor);
+                }
             }
         }
     }
 
+    // Moves the selection to an unused collision layer that it masks out,
+    // selected nodes stop colliding with each other but still hit everything else
+    fn isolate_selection(&mut self) {
+        let used = self
+            .world
+            .nodes
+            .values()
+            .fold(Layers::empty(), |used, n| used | n.category);
+        let Some(group) = (1..32).map(Layers::group).find(|&g| !used.contains(g)) else {
+            return;
+        };
+        for &n in self.selected_nodes.iter() {
+            let node = &mut self.world.nodes[n];
+            node.category = group;
+            node.mask = Layers::ALL - group;
+        }
+    }
+
     pub fn update(&mut self) {
         std::thread::sleep(std::time::Duration::from_millis(4));
 
         let app = unsafe { self.app.as_ref().unwrap() };
         let mx = app.mouse_x / self.world.scale();
         let my = app.mouse_y / self.world.scale();
-        while self.physics_cooldown.ready() {
-            let dt = self.physics_cooldown.delay.as_secs_f32();
-            self.world
-                .update(&mut self.integrator, dt * self.time_scale, 1);
-            self.physics_cooldown.next();
+        // Deterministic worlds take exactly one tick per frame so the wall clock never
+        // changes how far they get
+        let dt = self.physics_cooldown.delay.as_secs_f32();
+        let mut integrator = self.world.integrator;
+        let ticks = if self.world.deterministic {
+            self.physics_cooldown.reset();
+            1
+        } else {
+            let mut ticks = 0;
+            while self.physics_cooldown.ready() {
+                ticks += 1;
+                self.physics_cooldown.next();
+            }
+            ticks
+        };
+        for _ in 0..ticks {
+            self.world.update(&mut integrator, dt * self.time_scale, 1);
+            self.broken_links += self.world.events.len() as u32;
         }
         if app.key_pressed(KeyCode::Digit1) {
             self.selected_material = Material::Node;
         } else if app.key_pressed(KeyCode::Digit2) {
             self.selected_material = Material::Fixed;
         } else if app.key_pressed(KeyCode::Digit3) {
-            self.selected_material = Material::Rotor;
+            self.selected_material = Material::Motor;
         } else if app.key_pressed(KeyCode::Digit4) {
             self.selected_material = Material::Hydraulic;
         } else if app.key_pressed(KeyCode::Digit5) {
@@ -181,9 +367,230 @@ impl SimpleApp {
             self.selected_material = Material::Roller;
         } else if app.key_pressed(KeyCode::Digit7) {
             self.selected_material = Material::Rope;
+        } else if app.key_pressed(KeyCode::Digit8) {
+            self.selected_material = Material::Weight;
+        } else if app.key_pressed(KeyCode::Digit9) {
+            self.selected_material = Material::Pulley;
+        } else if app.key_pressed(KeyCode::Digit0) {
+            self.selected_material = Material::Gear;
+        }
+        if app.key_pressed(KeyCode::KeyL) {
+            self.world.link_collision = !self.world.link_collision;
+        }
+        if app.key_pressed(KeyCode::KeyK) {
+            self.world.skip_linked_collisions = !self.world.skip_linked_collisions;
+        }
+        if app.key_pressed(KeyCode::KeyG) {
+            self.isolate_selection();
+        } else if app.key_pressed(KeyCode::KeyH) {
+            for &n in self.selected_nodes.iter() {
+                self.world.nodes[n].category = Layers::DEFAULT;
+                self.world.nodes[n].mask = Layers::ALL;
+            }
+        }
+        if app.key_pressed(KeyCode::KeyS) {
+            self.show_stress = !self.show_stress;
+        }
+        if app.key_pressed(KeyCode::KeyJ) {
+            self.spring_preset = (self.spring_preset + 1) % SPRING_PRESETS.len();
+        }
+        if app.key_pressed(KeyCode::KeyB) {
+            // Strength applied to new links, cycles from unbreakable to weaker
+            let i = LINK_STRENGTHS
+                .iter()
+                .position(|&s| s == self.link_strength)
+                .map_or(0, |i| (i + 1) % LINK_STRENGTHS.len());
+            self.link_strength = LINK_STRENGTHS[i];
         }
         let intersecting_node = self.world.point_inside_node(mx, my);
         let intersecting_link = self.world.point_inside_link(mx, my);
+        let intersecting_terrain = self.world.point_inside_terrain(mx, my);
+        let intersecting_field = self.world.point_inside_field(mx, my);
+        // W, A and V place wind, attractor and vortex fields, N toggles gravity, X cycles drag
+        let center = Vec2::new(mx, my);
+        if app.key_pressed(KeyCode::KeyW) {
+            self.world.add_field(ForceField::Uniform {
+                center,
+                radius: FIELD_RADIUS,
+                force: Vec2::new(4.0, 0.0),
+            });
+        } else if app.key_pressed(KeyCode::KeyA) {
+            self.world.add_field(ForceField::Attractor {
+                center,
+                radius: FIELD_RADIUS,
+                strength: 8.0,
+            });
+        } else if app.key_pressed(KeyCode::KeyV) {
+            self.world.add_field(ForceField::Vortex {
+                center,
+                radius: FIELD_RADIUS,
+                strength: 8.0,
+            });
+        }
+        if app.key_pressed(KeyCode::KeyN) {
+            self.world.gravity = if self.world.gravity.len2() == 0.0 {
+                World::default().gravity
+            } else {
+                Vec2::ZERO
+            };
+        }
+        if app.key_pressed(KeyCode::KeyX) {
+            let i = LINEAR_DRAGS
+                .iter()
+                .position(|&d| d == self.world.linear_drag)
+                .map_or(0, |i| (i + 1) % LINEAR_DRAGS.len());
+            self.world.linear_drag = LINEAR_DRAGS[i];
+        }
+        // F cycles the integrator, I the link solver from impulses to XPBD in either order
+        // and Z its iteration count, period toggles adaptive substepping and comma
+        // deterministic mode
+        if app.key_pressed(KeyCode::Comma) {
+            self.world.deterministic = !self.world.deterministic;
+        }
+        if app.key_pressed(KeyCode::Period) {
+            self.world.adaptive = match self.world.adaptive {
+                Some(_) => None,
+                None => Some(AdaptiveSubsteps::default()),
+            };
+        }
+        if app.key_pressed(KeyCode::KeyF) {
+            let i = IntegratorKind::ALL
+                .iter()
+                .position(|&kind| kind == self.world.integrator)
+                .map_or(0, |i| (i + 1) % IntegratorKind::ALL.len());
+            self.world.integrator = IntegratorKind::ALL[i];
+        }
+        if app.key_pressed(KeyCode::KeyI) {
+            self.world.solver = match self.world.solver {
+                Solver::Impulse => Solver::Xpbd(Xpbd::default()),
+                Solver::Xpbd(xpbd) if xpbd.order == SolverOrder::GaussSeidel => {
+                    Solver::Xpbd(Xpbd {
+                        order: SolverOrder::Jacobi,
+                        ..xpbd
+                    })
+                }
+                Solver::Xpbd(_) => Solver::Impulse,
+            };
+        }
+        if let (true, Solver::Xpbd(xpbd)) = (app.key_pressed(KeyCode::KeyZ), &mut self.world.solver)
+        {
+            let i = XPBD_ITERATIONS
+                .iter()
+                .position(|&n| n == xpbd.iterations)
+                .map_or(0, |i| (i + 1) % XPBD_ITERATIONS.len());
+            xpbd.iterations = XPBD_ITERATIONS[i];
+        }
+        // Arrows extend and retract hydraulics while held, P cycles them through the periodic patterns
+        let manual = |drive: HydraulicControl| {
+            move |control: HydraulicControl| match control {
+                HydraulicControl::Pattern { .. } => control,
+                _ => drive,
+            }
+        };
+        if app.key_pressed(KeyCode::ArrowUp) {
+            self.drive_hydraulics(manual(HydraulicControl::Extend));
+        } else if app.key_pressed(KeyCode::ArrowDown) {
+            self.drive_hydraulics(manual(HydraulicControl::Retract));
+        } else if app.key_released(KeyCode::ArrowUp) || app.key_released(KeyCode::ArrowDown) {
+            self.drive_hydraulics(manual(HydraulicControl::Hold));
+        }
+        if app.key_pressed(KeyCode::KeyP) {
+            self.drive_hydraulics(|control| {
+                let wave = match control {
+                    HydraulicControl::Pattern { wave, .. } => match wave {
+                        Wave::Sine => Wave::Square,
+                        Wave::Square => Wave::PingPong,
+                        Wave::PingPong => return HydraulicControl::Hold,
+                    },
+                    _ => Wave::Sine,
+                };
+                HydraulicControl::Pattern {
+                    wave,
+                    period: HYDRAULIC_PERIOD,
+                    phase: 0.0,
+                }
+            });
+        }
+        // M toggles motors, R reverses them, brackets change their speed and minus/equal their torque
+        if app.key_pressed(KeyCode::KeyM) {
+            self.drive_motors(|m| m.enabled = !m.enabled);
+        }
+        if app.key_pressed(KeyCode::KeyR) {
+            self.drive_motors(|m| m.speed = -m.speed);
+        }
+        if app.key_pressed(KeyCode::BracketLeft) {
+            self.drive_motors(|m| m.speed /= MOTOR_STEP);
+        } else if app.key_pressed(KeyCode::BracketRight) {
+            self.drive_motors(|m| m.speed *= MOTOR_STEP);
+        }
+        if app.key_pressed(KeyCode::Minus) {
+            self.drive_motors(|m| m.max_torque /= MOTOR_STEP);
+        } else if app.key_pressed(KeyCode::Equal) {
+            self.drive_motors(|m| m.max_torque *= MOTOR_STEP);
+        }
+        // Holding T draws a terrain segment from where it was pressed to where it is released
+        if app.key_pressed(KeyCode::KeyT) {
+            self.terrain_start = Some(Vec2::new(mx, my));
+        } else if app.key_released(KeyCode::KeyT) {
+            if let Some(a) = self.terrain_start.take() {
+                let b = Vec2::new(mx, my);
+                if a.dist(&b) > 0.0 {
+                    self.world.add_terrain(Terrain {
+                        shape: Shape::Segment { a, b },
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        // Holding U over a node puts it on a track towards where U is released,
+        // Y makes the hovered node slide between the two other selected nodes, E toggles welding new links
+        // and O frees the hovered node from its constraints
+        if app.key_pressed(KeyCode::KeyU) {
+            self.track_node = intersecting_node;
+        } else if app.key_released(KeyCode::KeyU) {
+            let track = self.track_node.take();
+            if let Some((node, origin)) =
+                track.and_then(|id| Some((id, self.world.nodes.get(id)?.p)))
+            {
+                let to = Vec2::new(mx, my);
+                if origin.dist(&to) > 0.0 {
+                    self.world
+                        .add_constraint(Constraint::track(node, origin, to));
+                }
+            }
+        }
+        if let (true, Some(node)) = (app.key_pressed(KeyCode::KeyY), intersecting_node) {
+            let anchors: Vec<NodeId> = self
+                .selected_nodes
+                .iter()
+                .copied()
+                .filter(|&n| n != node)
+                .collect();
+            if let [a, b] = anchors[..] {
+                let len = self.world.nodes[a].p.dist(&self.world.nodes[b].p);
+                self.world.add_constraint(Constraint::Slider {
+                    node,
+                    a,
+                    b,
+                    min: 0.0,
+                    max: len,
+                });
+            }
+        }
+        if app.key_pressed(KeyCode::KeyE) {
+            self.weld = !self.weld;
+        }
+        // Q switches ropes between single links and chains of segments that drape
+        if app.key_pressed(KeyCode::KeyQ) {
+            self.chain_ropes = !self.chain_ropes;
+        }
+        if let (true, Some(node)) = (app.key_pressed(KeyCode::KeyO), intersecting_node) {
+            for constraint_id in self.world.node_constraints(node) {
+                if self.world.constraints[constraint_id].node() == node {
+                    self.world.remove_constraint(constraint_id);
+                }
+            }
+        }
         if app.mouse_pressed(MouseButton::Left) {
             if let Some(intersecting_node) = intersecting_node {
                 self.selected_node = Some(intersecting_node);
@@ -202,14 +609,16 @@ impl SimpleApp {
                         self.link_nodes(selected_node, intersecting_node);
                     }
                 } else if !app.key_down(KeyCode::ShiftLeft) {
-                    self.add_node(mx, my);
-                    self.link_nodes(selected_node, self.world.nodes.len() as u32 - 1);
+                    let node = self.add_node(mx, my);
+                    self.link_nodes(selected_node, node);
                 }
                 self.selected_node = None;
             }
         } else if app.mouse_pressed(MouseButton::Right)
             && intersecting_node.is_none()
             && intersecting_link.is_none()
+            && intersecting_terrain.is_none()
+            && intersecting_field.is_none()
             && self.selected_node.is_none()
         {
             self.selection_start = Some(Vec2::new(mx, my));
@@ -219,26 +628,42 @@ impl SimpleApp {
                 let selection_end = Vec2::new(mx, my);
                 let min = selection_start.min(&selection_end);
                 let max = selection_start.max(&selection_end);
-                for i in 0..self.world.nodes.len() {
-                    let n = &self.world.nodes[i];
+                for (id, n) in self.world.nodes.iter() {
                     if n.p.x >= min.x && n.p.y >= min.y && n.p.x <= max.x && n.p.y <= max.y {
-                        self.selected_nodes.push(i as u32);
+                        self.selected_nodes.push(id);
+                    }
+                }
+                // Touching a chain selects all of its segments
+                let chains: Vec<ChainId> = self
+                    .selected_nodes
+                    .iter()
+                    .filter_map(|n| self.world.node_chains.get(n).copied())
+                    .collect();
+                for chain_id in chains {
+                    for &n in self.world.chains[chain_id].inner() {
+                        if !self.selected_nodes.contains(&n) {
+                            self.selected_nodes.push(n);
+                        }
                     }
                 }
                 self.selection_start = None;
             } else if let Some(intersecting_node) = intersecting_node {
                 self.world.remove_node(intersecting_node);
-            } else if let Some(link_idx) = intersecting_link {
-                self.world.remove_link(link_idx);
+            } else if let Some(link_id) = intersecting_link {
+                self.world.remove_link(link_id);
+            } else if let Some(field_id) = intersecting_field {
+                self.world.remove_field(field_id);
+            } else if let Some(terrain_id) = intersecting_terrain {
+                self.world.remove_terrain(terrain_id);
             }
         }
         if app.mouse_down(MouseButton::Left) && app.key_down(KeyCode::ShiftLeft) {
             if let Some(selected_node) = self.selected_node {
-                let Vec2 { x, y } = self.world.nodes[selected_node as usize].p;
+                let Vec2 { x, y } = self.world.nodes[selected_node].p;
                 self.world
                     .move_node(selected_node, mx - x, my - y, self.time_scale == 0.0);
             } else if let Some(intersecting_node) = intersecting_node {
-                let Vec2 { x, y } = self.world.nodes[intersecting_node as usize].p;
+                let Vec2 { x, y } = self.world.nodes[intersecting_node].p;
                 self.world
                     .move_node(intersecting_node, mx - x, my - y, self.time_scale == 0.0);
             }
@@ -260,41 +685,21 @@ impl SimpleApp {
             self.selected_nodes.clear();
         }
 
-        for i in 0..self.world.nodes.len() {
-            let p = self.world.nodes[i].p;
-            if p.len() > 512.0 {
-                self.world.remove_node(i as u32);
-                break;
-            }
-        }
-
-        for &idx in self.world.node_remove_queue.iter() {
-            self.selected_nodes.retain(|n| *n != idx);
-            if let Some(selected_node) = self.selected_node {
-                if idx == selected_node {
-                    self.selected_node = None;
-                }
-            }
+        let far_node = self
+            .world
+            .nodes
+            .iter()
+            .find(|(_, n)| n.p.len() > 512.0)
+            .map(|(id, _)| id);
+        if let Some(far_node) = far_node {
+            self.world.remove_node(far_node);
         }
 
-        let node_swaps = self.world.flush();
+        self.world.flush();
 
-        for (idx, swap_idx) in node_swaps {
-            for selected_node in self.selected_nodes.iter_mut() {
-                if *selected_node == idx {
-                    *selected_node = swap_idx;
-                } else if *selected_node == swap_idx {
-                    *selected_node = idx;
-                }
-            }
-            if let Some(selected_node) = self.selected_node {
-                if selected_node == idx {
-                    self.selected_node = Some(swap_idx);
-                } else if selected_node == swap_idx {
-                    self.selected_node = Some(idx);
-                }
-            }
-        }
+        self.selected_nodes
+            .retain(|&n| self.world.nodes.contains(n));
+        self.selected_node = self.selected_node.filter(|&n| self.world.nodes.contains(n));
     }
 
     pub fn event(&mut self, _event: &WindowEvent) {}
@@ -323,7 +728,11 @@ impl SimpleApp {
         gfx.rect(0.0, 0.0, 1000.0, 1000.0);
         gfx.stroke_color = [255, 255, 255, 255];
         gfx.color = [255, 255, 255, 255];
-        self.world.render(gfx);
+        if self.show_stress {
+            self.world.render_stress(gfx);
+        } else {
+            self.world.render(gfx);
+        }
 
         let (selected_nodes, selected_links) = self.world.select(&self.selected_nodes);
         gfx.color = [160, 190, 255, 255];
@@ -343,16 +752,55 @@ impl SimpleApp {
 
         // Ghost Placement
         gfx.reset();
+        if let Some(a) = self.terrain_start {
+            gfx.color[3] = 64;
+            let terrain = Terrain {
+                shape: Shape::Segment {
+                    a,
+                    b: Vec2::new(mx, my),
+                },
+                ..Default::default()
+            };
+            self.world.render_terrain(&terrain, gfx);
+            gfx.color[3] = 255;
+        }
+        if let Some((a, wheel)) = self.pulley_rope {
+            if self.world.nodes.contains(a) && self.world.nodes.contains(wheel) {
+                gfx.color[3] = 64;
+                let rope = Link::Rope {
+                    n1: a,
+                    n2: wheel,
+                    dist: self.world.nodes[a].p.dist(&self.world.nodes[wheel].p),
+                    strength: Link::UNBREAKABLE,
+                };
+                self.world.render_link(&rope, None, &self.world.nodes, gfx);
+                gfx.color[3] = 255;
+            }
+        }
+        if let Some((id, node)) = self
+            .track_node
+            .and_then(|id| Some((id, self.world.nodes.get(id)?)))
+        {
+            gfx.color[3] = 64;
+            let track = Constraint::track(id, node.p, Vec2::new(mx, my));
+            self.world.render_constraint(&track, gfx);
+            gfx.color[3] = 255;
+        }
         if let Some(selected_node) = self.selected_node {
             if app.mouse_down(MouseButton::Left) {
                 gfx.color[3] = 64;
                 let node = Self::material_node(self.selected_material, mx, my);
                 let color = Self::material_color(self.selected_material);
                 self.world.render_node(&node, color, gfx);
-                let selected_node = self.world.nodes[selected_node as usize].clone();
+                let selected_node = self.world.nodes[selected_node].clone();
+                let dist = selected_node.p.dist(&node.p);
+                let mut nodes = SlotMap::new();
+                let n1 = nodes.insert(selected_node);
+                let n2 = nodes.insert(node);
                 self.world.render_link(
-                    &Self::material_link(self.selected_material, selected_node.p.dist(&node.p)),
-                    &vec![selected_node, node],
+                    &Self::material_link(self.selected_material, n1, n2, dist),
+                    None,
+                    &nodes,
                     gfx,
                 );
                 gfx.color[3] = 255;
@@ -406,6 +854,112 @@ impl SimpleApp {
             0.04,
         );
 
+        let strength = if self.link_strength == Link::UNBREAKABLE {
+            "unbreakable".to_string()
+        } else {
+            format!("{:.0}", self.link_strength)
+        };
+        gfx.text(
+            format!(
+                "Strength: {} (B), broken: {}, stress view (S): {}, link collision (L): {}",
+                strength,
+                self.broken_links,
+                if self.show_stress { "on" } else { "off" },
+                if self.world.link_collision {
+                    "on"
+                } else {
+                    "off"
+                }
+            )
+            .as_str(),
+            -0.95,
+            0.7,
+            0.04,
+        );
+
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        gfx.text(
+            format!(
+                "Linked collision (K): {}, gravity (N): {}, drag (X): {}, fields (W/A/V): {}, \
+                 spring (J): k {} c {}, hydraulics (Up/Down/P)",
+                on_off(!self.world.skip_linked_collisions),
+                on_off(self.world.gravity.len2() != 0.0),
+                self.world.linear_drag,
+                self.world.fields.len(),
+                SPRING_PRESETS[self.spring_preset].0,
+                SPRING_PRESETS[self.spring_preset].1
+            )
+            .as_str(),
+            -0.95,
+            0.6,
+            0.04,
+        );
+
+        let motors = self.world.nodes.values().filter(|n| n.motor()).count();
+        let stalled = self
+            .world
+            .motor_loads
+            .values()
+            .filter(|&&load| load > 1.0)
+            .count();
+        gfx.text(
+            format!(
+                "Motors: {}, stalled: {}, on/off (M), reverse (R), speed ([/]), torque (-/=), \
+                 constraints (U/Y/O): {}, weld (E): {}, chain ropes (Q): {}",
+                motors,
+                stalled,
+                self.world.constraints.len(),
+                on_off(self.weld),
+                on_off(self.chain_ropes)
+            )
+            .as_str(),
+            -0.95,
+            0.5,
+            0.04,
+        );
+
+        let solver = match self.world.solver {
+            Solver::Impulse => "impulse".to_string(),
+            Solver::Xpbd(xpbd) => format!(
+                "XPBD {}, {} iterations (Z)",
+                match xpbd.order {
+                    SolverOrder::GaussSeidel => "Gauss-Seidel",
+                    SolverOrder::Jacobi => "Jacobi",
+                },
+                xpbd.iterations
+            ),
+        };
+        gfx.text(
+            format!(
+                "Integrator (F): {}, solver (I): {}, substeps (.): {}{}",
+                self.world.integrator.name(),
+                solver,
+                self.world.substeps,
+                if self.world.adaptive.is_some() {
+                    " adaptive"
+                } else {
+                    ""
+                }
+            )
+            .as_str(),
+            -0.95,
+            0.4,
+            0.04,
+        );
+
+        gfx.text(
+            format!(
+                "Deterministic (,): {}, time: {:.3}, state hash: {:016x}",
+                on_off(self.world.deterministic),
+                self.world.time,
+                self.world.state_hash()
+            )
+            .as_str(),
+            -0.95,
+            0.3,
+            0.04,
+        );
+
         if let Some(selection_start) = self.selection_start {
             let selection_end = Vec2::new(app.mouse_x, app.mouse_y) / self.world.scale();
             let min = selection_start.min(&selection_end) * 0.5 * self.world.scale();
@@ -420,9 +974,8 @@ impl SimpleApp {
 
 impl Drop for SimpleApp {
     fn drop(&mut self) {
-        let mut file = File::create("assets/save.dat").expect("Failed to open save file to save");
         self.world
-            .serealize(&mut file)
+            .save(Self::save_path())
             .expect("Failed to save game");
     }
 }
@@ -0,0 +1,528 @@
+// Integrator accuracy harness, runs canonical scenes with every integrator and compares them
+// with their analytic answers
+//
+// cargo run --no-default-features --bin accuracy -- --seconds 20 --solver xpbd
+
+use silk_engine::{IntegratorKind, Link, Node, NodeId, Solver, SolverOrder, Vec2, World, Xpbd};
+use std::{f32::consts::PI, process};
+
+const USAGE: &str = "\
+Usage: accuracy [options]
+
+Runs the pendulum, double pendulum, hanging chain, spring-mass oscillator and loaded truss
+scenes with every integrator and reports, per scene and integrator:
+  energy drift  mechanical energy gained by the end over the largest kinetic energy seen,
+                negative when energy was lost
+  link error    largest stretch or compression of a rigid link or taut rope over the run
+  reference     the measured quantity, its analytic answer and the relative error,
+                periods for the oscillators, sag for the chain and static tip deflection for the truss
+
+Options:
+  --seconds <s>        Simulated time per run (default 20)
+  --dt <seconds>       Tick length (default 1/256)
+  --substeps <n>       Substeps per tick (default 1)
+  --solver <name>      impulse | xpbd | xpbd-jacobi (default impulse)
+  --iterations <n>     XPBD iterations per substep (default 8), needs an xpbd solver
+  --scene <name>       Run only pendulum | double_pendulum | chain | spring | truss
+  --integrator <name>  Run only euler | verlet | rk4
+  --csv                Print comma separated values instead of a table
+  --max-error <e>      Exit with status 1 when any relative error is larger than e";
+
+const GRAVITY: f32 = 6.0;
+
+struct Options {
+    seconds: f32,
+    dt: f32,
+    substeps: u32,
+    solver: Solver,
+    iterations: Option<u32>,
+    scene: Option<String>,
+    integrator: Option<IntegratorKind>,
+    csv: bool,
+    max_error: Option<f32>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            seconds: 20.0,
+            dt: 1.0 / 256.0,
+            substeps: 1,
+            solver: Solver::Impulse,
+            iterations: None,
+            scene: None,
+            integrator: None,
+            csv: false,
+            max_error: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--seconds" => options.seconds = parse_num(&value()?)?,
+                "--dt" => options.dt = parse_num(&value()?)?,
+                "--substeps" => options.substeps = parse_num::<u32>(&value()?)?.max(1),
+                "--solver" => {
+                    options.solver = match value()?.to_lowercase().as_str() {
+                        "impulse" => Solver::Impulse,
+                        "xpbd" => Solver::Xpbd(Xpbd::default()),
+                        "xpbd-jacobi" => Solver::Xpbd(Xpbd {
+                            order: SolverOrder::Jacobi,
+                            ..Default::default()
+                        }),
+                        solver => return Err(format!("Unknown solver {}", solver)),
+                    }
+                }
+                "--iterations" => options.iterations = Some(parse_num(&value()?)?),
+                "--scene" => {
+                    let name = value()?.to_lowercase().replace('-', "_");
+                    if !SCENES.iter().any(|(scene, _)| *scene == name) {
+                        return Err(format!("Unknown scene {}", name));
+                    }
+                    options.scene = Some(name);
+                }
+                "--integrator" => {
+                    let name = value()?.to_lowercase().replace('-', "_");
+                    let kind = IntegratorKind::from_name(&name)
+                        .ok_or(format!("Unknown integrator {}", name))?;
+                    options.integrator = Some(kind);
+                }
+                "--csv" => options.csv = true,
+                "--max-error" => options.max_error = Some(parse_num(&value()?)?),
+                "-h" | "--help" => return Err(String::new()),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+        if options.dt <= 0.0 || options.seconds <= 0.0 {
+            return Err("--dt and --seconds must be positive".to_string());
+        }
+        match (&mut options.solver, options.iterations) {
+            (Solver::Xpbd(xpbd), Some(iterations)) => xpbd.iterations = iterations,
+            (Solver::Impulse, Some(_)) => {
+                return Err("--iterations needs --solver xpbd or xpbd-jacobi".to_string());
+            }
+            (_, None) => {}
+        }
+        Ok(options)
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("Invalid number {}", s))
+}
+
+/// What a scene is checked against
+enum Reference {
+    /// Period of `signal` crossing zero upwards, in seconds
+    Period {
+        signal: Box<dyn Fn(&World) -> f32>,
+        period: f32,
+    },
+    /// Depth of `node` below `anchor` at the end of the run
+    Sag {
+        node: NodeId,
+        anchor: NodeId,
+        sag: f32,
+    },
+    /// Distance `node` ends up from where it started
+    Deflection {
+        node: NodeId,
+        start: Vec2,
+        deflection: f32,
+    },
+    /// Nothing analytic to compare with, only energy and links are checked
+    None,
+}
+
+struct Scene {
+    world: World,
+    reference: Reference,
+}
+
+fn world() -> World {
+    World {
+        gravity: Vec2::new(0.0, -GRAVITY),
+        skip_linked_collisions: true,
+        ..Default::default()
+    }
+}
+
+fn link(world: &mut World, n1: NodeId, n2: NodeId) {
+    world.link_node(Link::Link {
+        n1,
+        n2,
+        dist: 0.0,
+        strength: Link::UNBREAKABLE,
+    });
+}
+
+// Complete elliptic integral of the first kind by the arithmetic-geometric mean
+fn elliptic_k(k: f32) -> f32 {
+    let (mut a, mut b) = (1.0f64, (1.0 - k as f64 * k as f64).sqrt());
+    for _ in 0..16 {
+        (a, b) = ((a + b) / 2.0, (a * b).sqrt());
+    }
+    (std::f64::consts::PI / (2.0 * a)) as f32
+}
+
+// Unit pendulum released at 60 degrees, the period is exact for any amplitude
+fn pendulum() -> Scene {
+    let (length, angle) = (1.0f32, PI / 3.0);
+    let mut world = world();
+    let pivot = world.add(Node::new_fixed(0.0, 0.0));
+    let bob = world.add(Node::new(angle.sin() * length, -angle.cos() * length));
+    link(&mut world, pivot, bob);
+    let period = 4.0 * (length / GRAVITY).sqrt() * elliptic_k((angle / 2.0).sin());
+    Scene {
+        world,
+        reference: Reference::Period {
+            signal: Box::new(move |w| w.nodes[bob].p.x),
+            period,
+        },
+    }
+}
+
+// Two half unit arms released horizontally, chaotic so only energy and links are checked
+fn double_pendulum() -> Scene {
+    let mut world = world();
+    let pivot = world.add(Node::new_fixed(0.0, 0.0));
+    let elbow = world.add(Node::new(0.5, 0.0));
+    let hand = world.add(Node::new(1.0, 0.0));
+    link(&mut world, pivot, elbow);
+    link(&mut world, elbow, hand);
+    Scene {
+        world,
+        reference: Reference::None,
+    }
+}
+
+// Rope of 16 segments hanging between two pins, the sag of a catenary of the same length,
+// which the point masses approach as the segments get shorter, drag lets it settle
+fn chain() -> Scene {
+    let (span, length, segments) = (2.0f32, 2.6f32, 16);
+    let mut world = world();
+    world.linear_drag = 0.5;
+    let a = world.add(Node::new_fixed(-span / 2.0, 0.0));
+    let b = world.add(Node::new_fixed(span / 2.0, 0.0));
+    let chain = world.add_chain(a, b, segments, length).unwrap();
+    let middle = world.chains[chain].nodes[segments as usize / 2];
+    // 2 c sinh(span / 2c) = length, bisected on c
+    let (mut lo, mut hi) = (1e-3f32, 1e3f32);
+    for _ in 0..100 {
+        let c = (lo + hi) / 2.0;
+        if 2.0 * c * (span / (2.0 * c)).sinh() > length {
+            lo = c;
+        } else {
+            hi = c;
+        }
+    }
+    let c = (lo + hi) / 2.0;
+    Scene {
+        world,
+        reference: Reference::Sag {
+            node: middle,
+            anchor: a,
+            sag: c * ((span / (2.0 * c)).cosh() - 1.0),
+        },
+    }
+}
+
+// Unit mass on an undamped spring pulled 0.1 below its rest point, period 2 pi sqrt(m / k)
+fn spring() -> Scene {
+    let (stiffness, length) = (50.0f32, 0.5f32);
+    let mut world = world();
+    let anchor = world.add(Node::new_fixed(0.0, 0.0));
+    let rest = -length - GRAVITY / stiffness;
+    let mass = world.add(Node::new(0.0, rest - 0.1));
+    world.link_node(Link::Spring {
+        n1: anchor,
+        n2: mass,
+        dist: length,
+        stiffness,
+        damping: 0.0,
+        strength: Link::UNBREAKABLE,
+    });
+    Scene {
+        world,
+        reference: Reference::Period {
+            signal: Box::new(move |w| w.nodes[mass].p.y - rest),
+            period: 2.0 * PI / stiffness.sqrt(),
+        },
+    }
+}
+
+// Six bay cantilever truss of stiff damped springs pinned at one end with a heavy tip, drag
+// settles it at its static deflection, gravity is weak enough for that to stay well within
+// small deflection theory
+fn truss() -> Scene {
+    let (bays, bay) = (6, 0.2f32);
+    let mut world = World {
+        gravity: Vec2::new(0.0, -0.1),
+        linear_drag: 4.0,
+        ..world()
+    };
+    let mut columns = Vec::new();
+    for i in 0..=bays {
+        let x = i as f32 * bay;
+        let column = if i == 0 {
+            [Node::new_fixed(x, 0.0), Node::new_fixed(x, bay)]
+        } else if i == bays {
+            [Node::new_mass(x, 0.0, 4.0), Node::new_mass(x, bay, 4.0)]
+        } else {
+            [Node::new(x, 0.0), Node::new(x, bay)]
+        };
+        columns.push(column.map(|n| world.add(n)));
+    }
+    let mut member = |n1, n2| {
+        world.link_node(Link::Spring {
+            n1,
+            n2,
+            dist: 0.0,
+            stiffness: TRUSS_STIFFNESS,
+            damping: 20.0,
+            strength: Link::UNBREAKABLE,
+        });
+    };
+    for (i, &[low, high]) in columns.iter().enumerate() {
+        member(low, high);
+        if let Some(&[next_low, next_high]) = columns.get(i + 1) {
+            member(low, next_low);
+            member(high, next_high);
+            member(low, next_high);
+        }
+    }
+    let tip = columns[bays][0];
+    Scene {
+        reference: Reference::Deflection {
+            node: tip,
+            start: world.nodes[tip].p,
+            deflection: static_deflection(&world, tip).len(),
+        },
+        world,
+    }
+}
+
+const TRUSS_STIFFNESS: f32 = 20000.0;
+
+// Small deflection of `node` under gravity by virtual work, sum over members of the force from
+// gravity times the force from a unit load at `node` over the stiffness. Member forces come from
+// the equilibrium of every free node, so the truss must be statically determinate
+fn static_deflection(world: &World, node: NodeId) -> Vec2 {
+    let free: Vec<NodeId> = world
+        .nodes
+        .keys()
+        .filter(|&n| !world.nodes[n].fixed())
+        .collect();
+    let row = |n: NodeId| free.iter().position(|&f| f == n);
+    let members: Vec<_> = world
+        .links
+        .values()
+        .filter(|l| row(l.n1()).is_some() || row(l.n2()).is_some())
+        .collect();
+    assert_eq!(
+        members.len(),
+        free.len() * 2,
+        "Truss is not statically determinate"
+    );
+
+    // Tension in a member pulls both ends towards each other
+    let mut matrix = vec![vec![0.0f64; members.len()]; free.len() * 2];
+    for (m, l) in members.iter().enumerate() {
+        let (p1, p2) = (world.nodes[l.n1()].p, world.nodes[l.n2()].p);
+        let u = (p2 - p1).norm();
+        for (n, u) in [(l.n1(), u), (l.n2(), -u)] {
+            if let Some(r) = row(n) {
+                matrix[2 * r][m] = u.x as f64;
+                matrix[2 * r + 1][m] = u.y as f64;
+            }
+        }
+    }
+    // Member forces balancing `loads`, one force per free node
+    let forces = |loads: &dyn Fn(NodeId) -> Vec2| {
+        let rhs = free
+            .iter()
+            .flat_map(|&n| {
+                let f = loads(n);
+                [-f.x as f64, -f.y as f64]
+            })
+            .collect();
+        solve_linear(matrix.clone(), rhs)
+    };
+    let gravity = forces(&|n| world.gravity * world.nodes[n].mass);
+    let unit = |dir: Vec2| {
+        let virtual_forces = forces(&|n| if n == node { dir } else { Vec2::ZERO });
+        let work: f64 = members
+            .iter()
+            .enumerate()
+            .map(|(m, l)| {
+                let stiffness = match l {
+                    Link::Spring { stiffness, .. } => *stiffness as f64,
+                    _ => f64::INFINITY,
+                };
+                gravity[m] * virtual_forces[m] / stiffness
+            })
+            .sum();
+        work as f32
+    };
+    Vec2::new(unit(Vec2::new(1.0, 0.0)), unit(Vec2::new(0.0, 1.0)))
+}
+
+// Gaussian elimination with partial pivoting
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+            .unwrap_or(col);
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for r in col + 1..n {
+            let (above, below) = a.split_at_mut(r);
+            let (pivot_row, row) = (&above[col], &mut below[0]);
+            let f = row[col] / pivot_row[col];
+            for (x, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= f * p;
+            }
+            b[r] -= f * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for r in (0..n).rev() {
+        let sum: f64 = (r + 1..n).map(|c| a[r][c] * x[c]).sum();
+        x[r] = (b[r] - sum) / a[r][r];
+    }
+    x
+}
+
+type SceneFn = fn() -> Scene;
+
+const SCENES: [(&str, SceneFn); 5] = [
+    ("pendulum", pendulum),
+    ("double_pendulum", double_pendulum),
+    ("chain", chain),
+    ("spring", spring),
+    ("truss", truss),
+];
+
+struct Report {
+    energy_drift: f32,
+    link_error: f32,
+    /// Measured value, analytic value
+    reference: Option<(f32, f32)>,
+}
+
+fn run(mut scene: Scene, mut integrator: IntegratorKind, options: &Options) -> Report {
+    let world = &mut scene.world;
+    world.solver = options.solver;
+    world.integrator = integrator;
+    let start_energy = world.mechanical_energy();
+    let (mut max_kinetic, mut link_error) = (0.0f32, 0.0f32);
+    let mut crossings = Vec::new();
+    let mut last = match &scene.reference {
+        Reference::Period { signal, .. } => signal(world),
+        _ => 0.0,
+    };
+    let ticks = (options.seconds / options.dt).round() as u32;
+    for tick in 1..=ticks {
+        world.update(&mut integrator, options.dt, options.substeps);
+        world.flush();
+        max_kinetic = max_kinetic.max(world.kinetic_energy());
+        link_error = link_error.max(world.max_link_error());
+        if let Reference::Period { signal, .. } = &scene.reference {
+            let value = signal(world);
+            if last < 0.0 && value >= 0.0 {
+                let t = (tick as f32 - value / (value - last)) * options.dt;
+                crossings.push(t);
+            }
+            last = value;
+        }
+    }
+
+    let reference = match scene.reference {
+        Reference::Period { period, .. } => (crossings.len() >= 2).then(|| {
+            let measured =
+                (crossings[crossings.len() - 1] - crossings[0]) / (crossings.len() - 1) as f32;
+            (measured, period)
+        }),
+        Reference::Sag { node, anchor, sag } => {
+            Some((world.nodes[anchor].p.y - world.nodes[node].p.y, sag))
+        }
+        Reference::Deflection {
+            node,
+            start,
+            deflection,
+        } => Some((world.nodes[node].p.dist(&start), deflection)),
+        Reference::None => None,
+    };
+    Report {
+        energy_drift: (world.mechanical_energy() - start_energy) / max_kinetic.max(1e-6),
+        link_error,
+        reference,
+    }
+}
+
+fn main() {
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        if !err.is_empty() {
+            eprintln!("{}\n", err);
+        }
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    });
+
+    if options.csv {
+        println!("scene,integrator,energy_drift,link_error,measured,analytic,relative_error");
+    } else {
+        println!(
+            "{:<16} {:<17} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            "scene", "integrator", "energy drift", "link error", "measured", "analytic", "error"
+        );
+    }
+    let mut failed = 0;
+    for (name, scene) in SCENES {
+        if options.scene.as_ref().is_some_and(|s| s != name) {
+            continue;
+        }
+        for integrator in IntegratorKind::ALL {
+            if options.integrator.is_some_and(|i| i != integrator) {
+                continue;
+            }
+            let report = run(scene(), integrator, &options);
+            let (measured, analytic, error) = match report.reference {
+                Some((measured, analytic)) => {
+                    (measured, analytic, (measured - analytic) / analytic)
+                }
+                None => (f32::NAN, f32::NAN, f32::NAN),
+            };
+            if options.csv {
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    name,
+                    integrator.name(),
+                    report.energy_drift,
+                    report.link_error,
+                    measured,
+                    analytic,
+                    error
+                );
+            } else {
+                println!(
+                    "{:<16} {:<17} {:>12.5} {:>12.5} {:>12.5} {:>12.5} {:>12.5}",
+                    name,
+                    integrator.name(),
+                    report.energy_drift,
+                    report.link_error,
+                    measured,
+                    analytic,
+                    error
+                );
+            }
+            if options.max_error.is_some_and(|max| error.abs() > max) {
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} runs exceeded --max-error", failed);
+        process::exit(1);
+    }
+}
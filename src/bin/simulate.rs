@@ -0,0 +1,405 @@
+// Headless batch simulator, steps a saved world and emits per-step metrics
+//
+// cargo run --no-default-features --bin simulate -- assets/save.dat --steps 1000 --format json
+
+use silk_engine::{
+    AdaptiveSubsteps, IntegratorKind, Json, LinkId, NodeId, Solver, SolverOrder, World, WorldEvent,
+    Xpbd,
+};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    process,
+};
+
+const USAGE: &str = "\
+Usage: simulate <save file> [options]
+
+Save files ending in .json are read as text scenes, anything else as binary
+Broken links are reported on stderr and their columns are left empty afterwards
+Every step reports the world time and the kinetic energy of all nodes
+
+Options:
+  --steps <n>          Number of ticks to simulate (default 1000)
+  --dt <seconds>       Tick length (default 1/256)
+  --substeps <n>       Substeps per tick passed to World::update (default 1)
+  --adaptive           Pick substeps per tick from velocity, link error and energy drift,
+                       --substeps becomes the minimum and --max-substeps the maximum
+  --max-substeps <n>   Most substeps adaptive ticks take (default 64), needs --adaptive
+  --integrator <name>  euler | verlet | rk4 (default from the save file)
+  --solver <name>      impulse | xpbd | xpbd-jacobi (default from the save file)
+  --iterations <n>     XPBD iterations per substep (default 8), needs an xpbd solver
+                       from --solver or the save file
+  --hash               Add a column with the state hash of every step
+  --check <file>       Compare state hashes with an earlier --hash run in csv or json,
+                       report the first step that differs and exit with status 3
+  --format <name>      csv | json (default csv)
+  --output <file>      Write metrics to a file instead of stdout
+  --save <file>        Write the final world, format picked by extension";
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    Json,
+}
+
+struct Options {
+    path: String,
+    steps: u32,
+    dt: f32,
+    substeps: u32,
+    adaptive: Option<AdaptiveSubsteps>,
+    integrator: Option<IntegratorKind>,
+    solver: Option<Solver>,
+    iterations: Option<u32>,
+    hash: bool,
+    check: Option<String>,
+    format: Format,
+    output: Option<String>,
+    save: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            path: String::new(),
+            steps: 1000,
+            dt: 1.0 / 256.0,
+            substeps: 1,
+            adaptive: None,
+            integrator: None,
+            solver: None,
+            iterations: None,
+            hash: false,
+            check: None,
+            format: Format::Csv,
+            output: None,
+            save: None,
+        };
+        let mut path = None;
+        let (mut adaptive, mut max_substeps) = (false, None);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--steps" => options.steps = parse_num(&value()?)?,
+                "--dt" => options.dt = parse_num(&value()?)?,
+                "--substeps" => options.substeps = parse_num::<u32>(&value()?)?.max(1),
+                "--adaptive" => adaptive = true,
+                "--max-substeps" => max_substeps = Some(parse_num(&value()?)?),
+                "--integrator" => {
+                    let name = value()?.to_lowercase().replace('-', "_");
+                    let kind = IntegratorKind::from_name(&name)
+                        .ok_or(format!("Unknown integrator {}", name))?;
+                    options.integrator = Some(kind);
+                }
+                "--solver" => {
+                    let order = match value()?.to_lowercase().as_str() {
+                        "impulse" => None,
+                        "xpbd" => Some(SolverOrder::GaussSeidel),
+                        "xpbd-jacobi" => Some(SolverOrder::Jacobi),
+                        solver => return Err(format!("Unknown solver {}", solver)),
+                    };
+                    options.solver = Some(order.map_or(Solver::Impulse, |order| {
+                        Solver::Xpbd(Xpbd {
+                            order,
+                            ..Default::default()
+                        })
+                    }));
+                }
+                "--iterations" => options.iterations = Some(parse_num(&value()?)?),
+                "--hash" => options.hash = true,
+                "--check" => options.check = Some(value()?),
+                "--format" => {
+                    options.format = match value()?.to_lowercase().as_str() {
+                        "csv" => Format::Csv,
+                        "json" => Format::Json,
+                        format => return Err(format!("Unknown format {}", format)),
+                    }
+                }
+                "--output" => options.output = Some(value()?),
+                "--save" => options.save = Some(value()?),
+                "-h" | "--help" => return Err(String::new()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ if path.is_none() => path = Some(arg),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+        options.path = path.ok_or("Missing save file")?;
+        if max_substeps.is_some() && !adaptive {
+            return Err("--max-substeps needs --adaptive".to_string());
+        }
+        options.adaptive = adaptive.then(|| {
+            let defaults = AdaptiveSubsteps::default();
+            AdaptiveSubsteps {
+                max_substeps: max_substeps.unwrap_or(defaults.max_substeps),
+                ..defaults
+            }
+        });
+        Ok(options)
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("Invalid number {}", s))
+}
+
+// Columns follow the starting nodes and links so rows stay aligned as links break
+struct Columns {
+    nodes: Vec<NodeId>,
+    links: Vec<LinkId>,
+    hash: bool,
+}
+
+impl Columns {
+    fn new(world: &World, hash: bool) -> Self {
+        Self {
+            nodes: world.nodes.keys().collect(),
+            links: world.links.keys().collect(),
+            hash,
+        }
+    }
+}
+
+// State hashes of every step of an earlier run, from its hash column or json "hash" fields
+fn read_hashes(path: &str) -> Result<Vec<u64>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let parse_hash =
+        |s: &str| u64::from_str_radix(s, 16).map_err(|_| format!("Invalid hash {}", s));
+    if text.trim_start().starts_with('[') {
+        let json = Json::parse(&text).map_err(|err| err.to_string())?;
+        json.as_array()
+            .ok_or("Expected an array of steps")?
+            .iter()
+            .map(|step| {
+                parse_hash(
+                    step.get("hash")
+                        .and_then(Json::as_str)
+                        .ok_or("Missing hash")?,
+                )
+            })
+            .collect()
+    } else {
+        let mut lines = text.lines();
+        let header = lines.next().ok_or("Empty file")?;
+        let column = header
+            .split(',')
+            .position(|name| name == "hash")
+            .ok_or("Missing hash column")?;
+        lines
+            .map(|line| parse_hash(line.split(',').nth(column).unwrap_or_default()))
+            .collect()
+    }
+}
+
+fn write_header(out: &mut impl Write, columns: &Columns, format: Format) -> io::Result<()> {
+    match format {
+        Format::Csv => {
+            write!(out, "step,time,kinetic,substeps")?;
+            if columns.hash {
+                write!(out, ",hash")?;
+            }
+            for i in 0..columns.nodes.len() {
+                write!(out, ",node{}_x,node{}_y", i, i)?;
+            }
+            for i in 0..columns.links.len() {
+                write!(out, ",link{}_len", i)?;
+            }
+            writeln!(out)
+        }
+        Format::Json => writeln!(out, "["),
+    }
+}
+
+fn write_step(
+    out: &mut impl Write,
+    world: &World,
+    columns: &Columns,
+    format: Format,
+    step: u32,
+) -> io::Result<()> {
+    let kinetic = world.kinetic_energy();
+    let nodes = columns.nodes.iter().map(|&id| world.nodes.get(id));
+    let links = columns
+        .links
+        .iter()
+        .map(|&id| world.links.get(id).map(|l| world.link_length(l)));
+    match format {
+        Format::Csv => {
+            write!(
+                out,
+                "{},{},{},{}",
+                step, world.time, kinetic, world.substeps
+            )?;
+            if columns.hash {
+                write!(out, ",{:016x}", world.state_hash())?;
+            }
+            for n in nodes {
+                match n {
+                    Some(n) => write!(out, ",{},{}", n.p.x, n.p.y)?,
+                    None => write!(out, ",,")?,
+                }
+            }
+            for len in links {
+                match len {
+                    Some(len) => write!(out, ",{}", len)?,
+                    None => write!(out, ",")?,
+                }
+            }
+            writeln!(out)
+        }
+        Format::Json => {
+            if step > 0 {
+                writeln!(out, ",")?;
+            }
+            write!(
+                out,
+                "{{\"step\":{},\"time\":{},\"kinetic\":{},\"substeps\":{},",
+                step, world.time, kinetic, world.substeps
+            )?;
+            if columns.hash {
+                write!(out, "\"hash\":\"{:016x}\",", world.state_hash())?;
+            }
+            write!(out, "\"nodes\":[")?;
+            for (i, n) in nodes.enumerate() {
+                let sep = if i > 0 { "," } else { "" };
+                match n {
+                    Some(n) => write!(out, "{}[{},{}]", sep, n.p.x, n.p.y)?,
+                    None => write!(out, "{}null", sep)?,
+                }
+            }
+            write!(out, "],\"links\":[")?;
+            for (i, len) in links.enumerate() {
+                let sep = if i > 0 { "," } else { "" };
+                match len {
+                    Some(len) => write!(out, "{}{}", sep, len)?,
+                    None => write!(out, "{}null", sep)?,
+                }
+            }
+            write!(out, "]}}")
+        }
+    }
+}
+
+fn report_events(world: &World, columns: &Columns, step: u32) {
+    for event in &world.events {
+        match event {
+            WorldEvent::LinkBroke { link, force, .. } => {
+                let i = columns.links.iter().position(|l| l == link);
+                eprintln!(
+                    "Step {}: link {} broke under force {}",
+                    step,
+                    i.map_or("?".to_string(), |i| i.to_string()),
+                    force
+                );
+            }
+        }
+    }
+}
+
+// Returns the first step whose state hash differs from `expected`
+fn run(
+    options: &Options,
+    world: &mut World,
+    expected: &[u64],
+    out: &mut impl Write,
+) -> io::Result<Option<u32>> {
+    let mut integrator = world.integrator;
+    let columns = Columns::new(world, options.hash);
+    let diverged = |world: &World, step: u32| {
+        expected
+            .get(step as usize)
+            .is_some_and(|&hash| hash != world.state_hash())
+    };
+    write_header(out, &columns, options.format)?;
+    write_step(out, world, &columns, options.format, 0)?;
+    let mut divergence = diverged(world, 0).then_some(0);
+    for step in 1..=options.steps {
+        if divergence.is_some() {
+            break;
+        }
+        world.update(&mut integrator, options.dt, options.substeps);
+        report_events(world, &columns, step);
+        world.flush();
+        write_step(out, world, &columns, options.format, step)?;
+        divergence = diverged(world, step).then_some(step);
+    }
+    if let Format::Json = options.format {
+        writeln!(out, "\n]")?;
+    }
+    out.flush()?;
+    Ok(divergence)
+}
+
+fn main() {
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|err| {
+        if !err.is_empty() {
+            eprintln!("{}\n", err);
+        }
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    });
+
+    let mut world = World::load(&options.path).unwrap_or_else(|err| {
+        eprintln!("Failed to load {}: {}", options.path, err);
+        process::exit(1);
+    });
+    if options.adaptive.is_some() {
+        world.adaptive = options.adaptive;
+    }
+    if let Some(integrator) = options.integrator {
+        world.integrator = integrator;
+    }
+    if let Some(solver) = options.solver {
+        world.solver = solver;
+    }
+    match (&mut world.solver, options.iterations) {
+        (Solver::Xpbd(xpbd), Some(iterations)) => xpbd.iterations = iterations,
+        (Solver::Impulse, Some(_)) => {
+            eprintln!("--iterations needs an xpbd solver, the impulse solver has none\n");
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+        (_, None) => {}
+    }
+
+    let expected = match &options.check {
+        Some(path) => read_hashes(path).unwrap_or_else(|err| {
+            eprintln!("Failed to read hashes from {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+
+    let mut out: BufWriter<Box<dyn Write>> = match &options.output {
+        Some(path) => BufWriter::new(Box::new(File::create(path).unwrap_or_else(|err| {
+            eprintln!("Failed to create {}: {}", path, err);
+            process::exit(1);
+        }))),
+        None => BufWriter::new(Box::new(io::stdout().lock())),
+    };
+
+    let divergence = run(&options, &mut world, &expected, &mut out).unwrap_or_else(|err| {
+        eprintln!("Failed to write metrics: {}", err);
+        process::exit(1);
+    });
+
+    if let Some(path) = &options.save {
+        if let Err(err) = world.save(path) {
+            eprintln!("Failed to save {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.check {
+        match divergence {
+            Some(step) => {
+                eprintln!("Diverged from {} at step {}", path, step);
+                process::exit(3);
+            }
+            None => {
+                let steps = expected.len().min(options.steps as usize + 1);
+                eprintln!("Matched {} over {} steps", path, steps);
+            }
+        }
+    }
+}
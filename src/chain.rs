@@ -0,0 +1,46 @@
+use crate::{Handle, LinkId, NodeId, Vec2};
+
+pub type ChainId = Handle<Chain>;
+
+/// Rope made of a row of light nodes joined by rope links, handled as one object,
+/// `nodes` runs from one end to the other and includes both ends, which stay outside the chain
+#[derive(Clone, Debug)]
+pub struct Chain {
+    pub nodes: Vec<NodeId>,
+    pub links: Vec<LinkId>,
+}
+
+impl Chain {
+    /// Mass of every generated segment node
+    pub const SEGMENT_MASS: f32 = 0.1;
+
+    /// Segment nodes owned by the chain, without the ends
+    pub fn inner(&self) -> &[NodeId] {
+        &self.nodes[1..self.nodes.len() - 1]
+    }
+}
+
+/// Smooth curve through `points`, `steps` samples per span between two points
+pub fn catmull_rom(points: &[Vec2], steps: u32) -> Vec<Vec2> {
+    if points.len() < 2 {
+        return points.to_vec();
+    }
+    let at = |i: isize| points[i.clamp(0, points.len() as isize - 1) as usize];
+    let mut curve = Vec::new();
+    for i in 0..points.len() as isize - 1 {
+        let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+        for s in 0..steps {
+            let t = s as f32 / steps as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            curve.push(
+                (p1 * 2.0
+                    + (p2 - p0) * t
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+                    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+                    * 0.5,
+            );
+        }
+    }
+    curve.push(points[points.len() - 1]);
+    curve
+}
@@ -0,0 +1,147 @@
+use crate::{Handle, NodeId, Vec2};
+
+pub type ConstraintId = Handle<Constraint>;
+
+/// Restriction on a node's motion beyond the pairwise distances kept by links
+#[derive(Clone, Debug)]
+pub enum Constraint {
+    /// Keeps `node` on the line through `origin` along the unit vector `dir`,
+    /// `min` and `max` limit how far along `dir` it travels from `origin`
+    Track {
+        node: NodeId,
+        origin: Vec2,
+        dir: Vec2,
+        min: f32,
+        max: f32,
+    },
+    /// Keeps `node` on the line through nodes `a` and `b`, limits are measured from `a` towards `b`
+    Slider {
+        node: NodeId,
+        a: NodeId,
+        b: NodeId,
+        min: f32,
+        max: f32,
+    },
+    /// Holds the angle from arm `node`-`a` to arm `node`-`b` at `angle`, counter-clockwise positive,
+    /// a finite `stiffness` in torque per radian lets it flex until the offset reaches `min` or `max`
+    Angle {
+        node: NodeId,
+        a: NodeId,
+        b: NodeId,
+        angle: f32,
+        stiffness: f32,
+        min: f32,
+        max: f32,
+    },
+    /// Rope from `a` over the pulley at `node` to `b`, keeps `|a - node| + ratio * |b - node|`
+    /// at most `length` so pulling one end in lets the other out
+    Pulley {
+        node: NodeId,
+        a: NodeId,
+        b: NodeId,
+        length: f32,
+        ratio: f32,
+    },
+    /// Meshes the gear at hub `node` with the one at hub `other`, which turns the opposite way
+    /// `ratio` times as fast, a gear's spin is that of the nodes linked to its hub
+    Gear {
+        node: NodeId,
+        other: NodeId,
+        ratio: f32,
+    },
+}
+
+/// Angle rotating `from` onto `to` in -PI..=PI, counter-clockwise positive
+pub fn signed_angle(from: Vec2, to: Vec2) -> f32 {
+    from.cross(&to).atan2(from.dot(&to))
+}
+
+/// Whether a track direction can be normalised, false for zero, NaN or infinite lengths
+pub fn valid_dir(dir: Vec2) -> bool {
+    dir.len2() > 0.0 && dir.len2().is_finite()
+}
+
+/// Wraps an angle into -PI..=PI
+pub fn wrap_angle(a: f32) -> f32 {
+    use std::f32::consts::{PI, TAU};
+    a - ((a + PI) / TAU).floor() * TAU
+}
+
+impl Constraint {
+    /// Limits of a constraint free to slide along its whole line
+    pub const UNLIMITED: (f32, f32) = (f32::NEG_INFINITY, f32::INFINITY);
+    /// Stiffness of an angle that never flexes, a weld
+    pub const RIGID: f32 = f32::INFINITY;
+
+    /// Track through `origin` towards `to`, `dir` is left at zero when the points coincide
+    pub fn track(node: NodeId, origin: Vec2, to: Vec2) -> Self {
+        let dir = to - origin;
+        Constraint::Track {
+            node,
+            origin,
+            dir: if dir.len2() == 0.0 { dir } else { dir.norm() },
+            min: Self::UNLIMITED.0,
+            max: Self::UNLIMITED.1,
+        }
+    }
+
+    /// Constrained node, the pivot of an angle, the wheel of a pulley or the driving gear's hub
+    pub fn node(&self) -> NodeId {
+        match self {
+            Constraint::Track { node, .. }
+            | Constraint::Slider { node, .. }
+            | Constraint::Angle { node, .. }
+            | Constraint::Pulley { node, .. }
+            | Constraint::Gear { node, .. } => *node,
+        }
+    }
+
+    pub fn involves(&self, n: NodeId) -> bool {
+        match *self {
+            Constraint::Track { node, .. } => node == n,
+            Constraint::Slider { node, a, b, .. }
+            | Constraint::Angle { node, a, b, .. }
+            | Constraint::Pulley { node, a, b, .. } => node == n || a == n || b == n,
+            Constraint::Gear { node, other, .. } => node == n || other == n,
+        }
+    }
+
+    /// Whether the constraint acts on the link between `n1` and `n2`, angles go away with their arms
+    pub fn uses_link(&self, n1: NodeId, n2: NodeId) -> bool {
+        let Constraint::Angle { node, a, b, .. } = *self else {
+            return false;
+        };
+        [(node, a), (node, b)]
+            .iter()
+            .any(|&pair| pair == (n1, n2) || pair == (n2, n1))
+    }
+
+    /// Travel limits of lines, flex limits around the rest angle of angles,
+    /// pulleys and gears are always unlimited
+    pub fn limits(&self) -> (f32, f32) {
+        match *self {
+            Constraint::Track { min, max, .. }
+            | Constraint::Slider { min, max, .. }
+            | Constraint::Angle { min, max, .. } => (min, max),
+            Constraint::Pulley { .. } | Constraint::Gear { .. } => Self::UNLIMITED,
+        }
+    }
+
+    /// Limits given in either order are sorted, a NaN bound is replaced by the other one
+    pub fn set_limits(&mut self, new_min: f32, new_max: f32) {
+        let limits = (new_min.min(new_max), new_max.max(new_min));
+        match self {
+            Constraint::Track { min, max, .. }
+            | Constraint::Slider { min, max, .. }
+            | Constraint::Angle { min, max, .. } => (*min, *max) = limits,
+            Constraint::Pulley { .. } | Constraint::Gear { .. } => {}
+        }
+    }
+
+    /// Shifts geometry anchored in the world, node anchors move with their nodes
+    pub fn move_by(&mut self, x: f32, y: f32) {
+        if let Constraint::Track { origin, .. } = self {
+            *origin += Vec2::new(x, y);
+        }
+    }
+}
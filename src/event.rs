@@ -32,6 +32,12 @@ pub struct Dispatcher<EventType: 'static> {
     subscribed_member_functions: Vec<Box<dyn HandlerFunctionBase<EventType>>>,
 }
 
+impl<EventType> Default for Dispatcher<EventType> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<EventType> Dispatcher<EventType> {
     pub fn new() -> Self {
         Self {
@@ -59,7 +65,8 @@ impl<EventType> Dispatcher<EventType> {
     }
 
     pub fn unsubscribe_func(&mut self, function: fn(&EventType)) {
-        self.subscribed_functions.retain(|&f| f != function);
+        self.subscribed_functions
+            .retain(|&f| !std::ptr::fn_addr_eq(f, function));
     }
 
     pub fn unsubscribe<T: 'static>(&mut self, instance: &T, member_function: fn(&T, &EventType)) {
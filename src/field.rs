@@ -0,0 +1,72 @@
+use crate::{Handle, Vec2};
+
+pub type FieldId = Handle<ForceField>;
+
+/// Force region acting on every node within `radius` of `center`,
+/// heavier nodes respond less since fields push with a force rather than an acceleration
+#[derive(Clone, Debug)]
+pub enum ForceField {
+    /// Constant push, e.g. wind
+    Uniform {
+        center: Vec2,
+        radius: f32,
+        force: Vec2,
+    },
+    /// Pulls towards `center`, negative strength repels
+    Attractor {
+        center: Vec2,
+        radius: f32,
+        strength: f32,
+    },
+    /// Swirls around `center`, counter-clockwise for positive strength
+    Vortex {
+        center: Vec2,
+        radius: f32,
+        strength: f32,
+    },
+}
+
+impl ForceField {
+    /// Radius of a field that covers the whole world
+    pub const UNBOUNDED: f32 = f32::INFINITY;
+
+    pub fn center(&self) -> Vec2 {
+        match self {
+            ForceField::Uniform { center, .. }
+            | ForceField::Attractor { center, .. }
+            | ForceField::Vortex { center, .. } => *center,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match self {
+            ForceField::Uniform { radius, .. }
+            | ForceField::Attractor { radius, .. }
+            | ForceField::Vortex { radius, .. } => *radius,
+        }
+    }
+
+    pub fn set_center(&mut self, p: Vec2) {
+        match self {
+            ForceField::Uniform { center, .. }
+            | ForceField::Attractor { center, .. }
+            | ForceField::Vortex { center, .. } => *center = p,
+        }
+    }
+
+    pub fn force_at(&self, p: Vec2) -> Vec2 {
+        let to_center = self.center() - p;
+        let dist = to_center.len();
+        if dist > self.radius() {
+            return Vec2::ZERO;
+        }
+        match *self {
+            ForceField::Uniform { force, .. } => force,
+            ForceField::Attractor { strength, .. } if dist > 0.0 => to_center / dist * strength,
+            ForceField::Vortex { strength, .. } if dist > 0.0 => {
+                -to_center.rot90() / dist * strength
+            }
+            ForceField::Attractor { .. } | ForceField::Vortex { .. } => Vec2::ZERO,
+        }
+    }
+}
@@ -5,8 +5,8 @@ pub struct HashGrid {
 }
 
 impl HashGrid {
-    pub fn new(points: &Vec<(f32, f32)>, cell_size: f32) -> Self {
-        if points.len() == 0 {
+    pub fn new(points: &[(f32, f32)], cell_size: f32) -> Self {
+        if points.is_empty() {
             return Self {
                 cell_size,
                 point_cell_keys: Vec::new(),
@@ -25,14 +25,15 @@ impl HashGrid {
             point_cell_keys[i] = (i as u32, key);
         }
 
-        point_cell_keys.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+        // Points of a cell stay in index order so lookups always list them the same way
+        point_cell_keys.sort_unstable_by_key(|&(i, key)| (key, i));
 
-        let (_, last_key) = point_cell_keys[0];
+        let (_, mut last_key) = point_cell_keys[0];
         cell_start_indices[last_key as usize] = 0;
-        for i in 1..point_cell_keys.len() {
-            let (_, key) = point_cell_keys[i];
+        for (i, &(_, key)) in point_cell_keys.iter().enumerate().skip(1) {
             if last_key != key {
                 cell_start_indices[key as usize] = i as u32;
+                last_key = key;
             }
         }
 
@@ -51,20 +52,26 @@ impl HashGrid {
         (cx.wrapping_mul(15823)
             .wrapping_add(cy.wrapping_mul(9737333))
             % size)
-            .abs() as u32
+            .unsigned_abs()
     }
 
     pub fn find(&self, x: f32, y: f32) -> Vec<u32> {
-        if self.point_cell_keys.len() == 0 {
+        if self.point_cell_keys.is_empty() {
             return Vec::new();
         }
 
         let mut indices = Vec::new();
+        let mut keys = Vec::with_capacity(9);
         for i in -1..=1 {
             for j in -1..=1 {
                 let (cx, cy) = self.to_cell_coord(x, y);
                 let (cx, cy) = (cx + i, cy + j);
                 let key = Self::cell_key(cx, cy, self.point_cell_keys.len() as i32);
+                // Neighbour cells can hash to the same key, its points are only listed once
+                if keys.contains(&key) {
+                    continue;
+                }
+                keys.push(key);
                 let start = self.cell_start_indices[key as usize];
                 if start == u32::MAX {
                     continue;
@@ -81,3 +88,59 @@ impl HashGrid {
         indices
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Scattered points, several sharing cells, some at negative coordinates
+    fn points() -> Vec<(f32, f32)> {
+        (0..200)
+            .map(|i| {
+                let t = i as f32;
+                ((t * 0.37).sin() * 3.0, (t * 0.91).cos() * 2.0 + t * 0.01)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_every_neighbour() {
+        let points = points();
+        let grid = HashGrid::new(&points, 0.25);
+        for &(x, y) in &points {
+            let found = grid.find(x, y);
+            for (j, &(px, py)) in points.iter().enumerate() {
+                if (px - x).abs() < 0.25 && (py - y).abs() < 0.25 {
+                    assert!(
+                        found.contains(&(j as u32)),
+                        "missed {} near ({}, {})",
+                        j,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lists_every_point_of_a_cell_in_order() {
+        let grid = HashGrid::new(&[(0.1, 0.1), (0.2, 0.3), (0.4, 0.2)], 1.0);
+        assert_eq!(grid.find(0.5, 0.5), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn lists_each_point_once() {
+        // One point makes every neighbour cell share its key
+        assert_eq!(HashGrid::new(&[(0.5, 0.5)], 1.0).find(0.5, 0.5), vec![0]);
+        let points = points();
+        let grid = HashGrid::new(&points, 0.25);
+        for &(x, y) in &points {
+            let mut found = grid.find(x, y);
+            let len = found.len();
+            found.sort_unstable();
+            found.dedup();
+            assert_eq!(found.len(), len);
+        }
+    }
+}
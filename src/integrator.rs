@@ -5,6 +5,69 @@ pub trait Integrator {
     fn solve(&mut self, world: &mut World);
 }
 
+/// Integrator picked at runtime and saved with the world
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntegratorKind {
+    /// Semi-implicit (symplectic) Euler
+    #[default]
+    Euler,
+    Verlet,
+    RK4,
+}
+
+impl IntegratorKind {
+    pub const ALL: [Self; 3] = [Self::Euler, Self::Verlet, Self::RK4];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Euler => "euler",
+            Self::Verlet => "verlet",
+            Self::RK4 => "rk4",
+        }
+    }
+
+    /// Also takes "symplectic_euler", a reordered copy of `Euler` older scenes may name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "symplectic_euler" => Some(Self::Euler),
+            name => Self::ALL.into_iter().find(|kind| kind.name() == name),
+        }
+    }
+
+    /// Tag in save files, 1 was the reordered copy of `Euler` and loads as it
+    pub const fn tag(self) -> u8 {
+        match self {
+            Self::Euler => 0,
+            Self::Verlet => 2,
+            Self::RK4 => 3,
+        }
+    }
+
+    pub const fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 | 1 => Some(Self::Euler),
+            2 => Some(Self::Verlet),
+            3 => Some(Self::RK4),
+            _ => None,
+        }
+    }
+}
+
+impl Integrator for IntegratorKind {
+    fn new() -> Self {
+        Self::default()
+    }
+    fn solve(&mut self, world: &mut World) {
+        match self {
+            Self::Euler => Euler.solve(world),
+            Self::Verlet => Verlet.solve(world),
+            Self::RK4 => RK4.solve(world),
+        }
+    }
+}
+
+/// Semi-implicit (symplectic) Euler, velocities take the step first and positions move
+/// with the new velocities
 #[derive(Default)]
 pub struct Euler;
 impl Integrator for Euler {
@@ -13,13 +76,33 @@ impl Integrator for Euler {
     }
     fn solve(&mut self, world: &mut World) {
         world.step();
-        for n in world.nodes.iter_mut() {
+        for n in world.nodes.values_mut() {
             n.p += n.v * world.dt;
         }
     }
 }
 
-// TODO: Figure out other integrators, tho it's working well for now
+/// Position Verlet, positions drift half a step on either side of the step so forces,
+/// contacts and links act at the midpoint, second order where Euler is first
+#[derive(Default)]
+pub struct Verlet;
+impl Integrator for Verlet {
+    fn new() -> Self {
+        Self
+    }
+    fn solve(&mut self, world: &mut World) {
+        for n in world.nodes.values_mut() {
+            n.p += n.v * world.dt * 0.5;
+        }
+        world.step();
+        for n in world.nodes.values_mut() {
+            n.p += n.v * world.dt * 0.5;
+        }
+    }
+}
+
+/// Classic fourth order Runge-Kutta with the whole step as the derivative, runs it four times
+/// per tick from the start state nudged by the previous stage, the most expensive
 #[derive(Default)]
 pub struct RK4;
 
@@ -29,50 +112,32 @@ impl Integrator for RK4 {
     }
 
     fn solve(&mut self, world: &mut World) {
-        let p0: Vec<Vec2> = world.nodes.iter().map(|n| n.p).collect();
-        let v0: Vec<Vec2> = world.nodes.iter().map(|n| n.v).collect();
-        world.step();
-        let p1: Vec<Vec2> = world
-            .nodes
-            .iter()
-            .enumerate()
-            .map(|(i, n)| (n.p - p0[i]))
-            .collect();
-        let v1: Vec<Vec2> = world
-            .nodes
-            .iter()
-            .enumerate()
-            .map(|(i, n)| (n.v - v0[i]))
-            .collect();
-        let mut step = |p1: &[Vec2], v1: &[Vec2], m: f32| -> (Vec<Vec2>, Vec<Vec2>) {
-            for i in 0..world.nodes.len() {
-                world.nodes[i].p = p0[i] + p1[i] * m;
-                world.nodes[i].v = v0[i] + v1[i] * m;
+        let p0: Vec<Vec2> = world.nodes.values().map(|n| n.p).collect();
+        let v0: Vec<Vec2> = world.nodes.values().map(|n| n.v).collect();
+        let dt = world.dt;
+        // Position and velocity change over a whole tick from the start state plus `m` times
+        // the previous stage, positions drift with the stage velocity on top of the step's pushes
+        let mut stage = |k: Option<&(Vec<Vec2>, Vec<Vec2>)>, m: f32| {
+            for (i, n) in world.nodes.values_mut().enumerate() {
+                n.p = p0[i] + k.map_or(Vec2::ZERO, |k| k.0[i] * m);
+                n.v = v0[i] + k.map_or(Vec2::ZERO, |k| k.1[i] * m);
             }
-            world.dt *= m;
+            let start: Vec<(Vec2, Vec2)> = world.nodes.values().map(|n| (n.p, n.v)).collect();
             world.step();
-            let p1 = world
+            world
                 .nodes
-                .iter()
-                .enumerate()
-                .map(|(i, n)| (n.p - p0[i] - p1[i] * m))
-                .collect();
-            let v1 = world
-                .nodes
-                .iter()
-                .enumerate()
-                .map(|(i, n)| (n.v - v0[i] - v1[i] * m))
-                .collect();
-            world.dt /= m;
-            (p1, v1)
+                .values()
+                .zip(start)
+                .map(|(n, (p, v))| (n.p - p + v * dt, n.v - v))
+                .unzip::<_, _, Vec<Vec2>, Vec<Vec2>>()
         };
-        let (p2, v2) = step(&p1, &v1, 0.5);
-        let (p3, v3) = step(&p2, &v2, 0.5);
-        let (p4, v4) = step(&p3, &v3, 1.0);
-        world.nodes.iter_mut().enumerate().for_each(|(i, n)| {
-            n.p = p0[i] + (p1[i] + p2[i] * 2.0 + p3[i] * 2.0 + p4[i]) / 6.0;
-            n.v = v0[i] + (v1[i] + v2[i] * 2.0 + v3[i] * 2.0 + v4[i]) / 6.0;
-            n.p += n.v * world.dt;
+        let k1 = stage(None, 0.0);
+        let k2 = stage(Some(&k1), 0.5);
+        let k3 = stage(Some(&k2), 0.5);
+        let k4 = stage(Some(&k3), 1.0);
+        world.nodes.values_mut().enumerate().for_each(|(i, n)| {
+            n.p = p0[i] + (k1.0[i] + k2.0[i] * 2.0 + k3.0[i] * 2.0 + k4.0[i]) / 6.0;
+            n.v = v0[i] + (k1.1[i] + k2.1[i] * 2.0 + k3.1[i] * 2.0 + k4.1[i]) / 6.0;
         });
     }
 }
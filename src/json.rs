@@ -0,0 +1,505 @@
+use std::fmt::{self, Write};
+
+/// Minimal JSON document model, numbers keep their source text so f32 values round-trip exactly
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.message, self.line, self.column)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<f32> for Json {
+    fn from(v: f32) -> Self {
+        if v.is_finite() {
+            Json::Number(v.to_string())
+        } else {
+            Json::Null
+        }
+    }
+}
+
+impl From<u32> for Json {
+    fn from(v: u32) -> Self {
+        Json::Number(v.to_string())
+    }
+}
+
+impl From<&str> for Json {
+    fn from(v: &str) -> Self {
+        Json::String(v.to_string())
+    }
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser {
+            src: src.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.src.len() {
+            return Err(parser.error("Trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Pretty prints with two space indentation, arrays of scalars stay on one line
+    pub fn write_pretty(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Array(items) if items.iter().all(|v| !v.is_container()) => {
+                out.push('[');
+                for (i, v) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    v.write_pretty(out, indent);
+                }
+                out.push(']');
+            }
+            Json::Array(items) => {
+                out.push('[');
+                for (i, v) in items.iter().enumerate() {
+                    out.push_str(if i > 0 { ",\n" } else { "\n" });
+                    push_indent(out, indent + 1);
+                    v.write_pretty(out, indent + 1);
+                }
+                out.push('\n');
+                push_indent(out, indent);
+                out.push(']');
+            }
+            Json::Object(fields) if fields.iter().all(|(_, v)| !v.is_container()) => {
+                out.push('{');
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    out.push_str(if i > 0 { ", " } else { " " });
+                    write_string(out, k);
+                    out.push_str(": ");
+                    v.write_pretty(out, indent);
+                }
+                out.push_str(if fields.is_empty() { "}" } else { " }" });
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    out.push_str(if i > 0 { ",\n" } else { "\n" });
+                    push_indent(out, indent + 1);
+                    write_string(out, k);
+                    out.push_str(": ");
+                    v.write_pretty(out, indent + 1);
+                }
+                out.push('\n');
+                push_indent(out, indent);
+                out.push('}');
+            }
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Number(n) => out.push_str(n),
+            Json::String(s) => write_string(out, s),
+        }
+    }
+
+    // Objects whose values are all scalars or scalar arrays print on a single line
+    fn is_container(&self) -> bool {
+        match self {
+            Json::Array(items) => items.iter().any(|v| v.is_container()),
+            Json::Object(..) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        f.write_str(&out)
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> JsonError {
+        let before = &self.src[..self.pos.min(self.src.len())];
+        let line = before.iter().filter(|&&c| c == b'\n').count() as u32 + 1;
+        let line_start = before
+            .iter()
+            .rposition(|&c| c == b'\n')
+            .map_or(0, |i| i + 1);
+        JsonError {
+            line,
+            column: (self.pos - line_start) as u32 + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.src.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), JsonError> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", c as char)))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        if self.src[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("Unexpected token"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("Expected object key"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        while self.pos < self.src.len()
+            && matches!(
+                self.src[self.pos],
+                b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+            )
+        {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+        if text.parse::<f64>().is_err() {
+            self.pos = start;
+            return Err(self.error("Invalid number"));
+        }
+        Ok(Json::Number(text.to_string()))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let Some(&c) = self.src.get(self.pos) else {
+                return Err(self.error("Unterminated string"));
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&esc) = self.src.get(self.pos) else {
+                        return Err(self.error("Unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match esc {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    let mut buf = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("Invalid UTF-8 in string"))
+    }
+
+    /// Reads the digits after a `\u`, joining a UTF-16 surrogate pair into one character
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if (0xDC00..0xE000).contains(&high) {
+            return Err(self.error("Unpaired surrogate in unicode escape"));
+        }
+        if !(0xD800..0xDC00).contains(&high) {
+            return Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        if self.src.get(self.pos..self.pos + 2) != Some(&b"\\u"[..]) {
+            return Err(self.error("Unpaired surrogate in unicode escape"));
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("Unpaired surrogate in unicode escape"));
+        }
+        let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        Ok(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let hex = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u32::from_str_radix(h, 16).ok())
+            .ok_or_else(|| self.error("Invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_escapes() {
+        let json = Json::parse(r#""q\" b\\ s\/ \b\f\n\r\t \u0041\u00e9""#).unwrap();
+        assert_eq!(json.as_str(), Some("q\" b\\ s/ \u{8}\u{c}\n\r\t Aé"));
+    }
+
+    #[test]
+    fn joins_surrogate_pairs() {
+        let json = Json::parse(r#""\uD83D\uDE00 \ud83d\ude00""#).unwrap();
+        assert_eq!(json.as_str(), Some("\u{1F600} \u{1F600}"));
+        for lone in [
+            r#""\uD83D""#,
+            r#""\uD83Dx""#,
+            r#""\uD83D\u0041""#,
+            r#""\uDE00""#,
+        ] {
+            let err = Json::parse(lone).unwrap_err();
+            assert!(err.to_string().contains("Unpaired surrogate"), "{}", lone);
+        }
+        assert!(Json::parse(r#""\u+041""#).is_err());
+    }
+
+    #[test]
+    fn writes_escapes_back() {
+        let s = "q\" b\\ \n\r\t \u{1} é";
+        let json = Json::String(s.to_string());
+        assert_eq!(json.to_string(), r#""q\" b\\ \n\r\t \u0001 é""#);
+        assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    }
+
+    #[test]
+    fn parses_numbers() {
+        for (text, value) in [
+            ("0", 0.0),
+            ("-1.5", -1.5),
+            ("1e3", 1000.0),
+            ("2.5E-2", 0.025),
+        ] {
+            let json = Json::parse(text).unwrap();
+            assert_eq!(json, Json::Number(text.to_string()));
+            assert_eq!(json.as_f32(), Some(value));
+        }
+        assert_eq!(Json::parse("3").unwrap().as_u32(), Some(3));
+        assert_eq!(Json::parse("-3").unwrap().as_u32(), None);
+        assert_eq!(Json::parse("1.5").unwrap().as_u32(), None);
+    }
+
+    #[test]
+    fn f32_round_trips_exactly() {
+        for v in [0.1f32, -1.0 / 3.0, f32::MIN_POSITIVE, f32::MAX, 1e-45] {
+            let text = Json::from(v).to_string();
+            assert_eq!(Json::parse(&text).unwrap().as_f32(), Some(v));
+        }
+        assert_eq!(Json::from(f32::NAN), Json::Null);
+        assert_eq!(Json::from(f32::INFINITY), Json::Null);
+    }
+
+    #[test]
+    fn parses_nesting() {
+        let json =
+            Json::parse(r#" { "a": [1, [2, {"b": null}]], "c": {"d": true}, "e": [] } "#).unwrap();
+        let a = json.get("a").and_then(Json::as_array).unwrap();
+        assert_eq!(a[0].as_u32(), Some(1));
+        let inner = a[1].as_array().unwrap();
+        assert_eq!(inner[1].get("b"), Some(&Json::Null));
+        assert_eq!(
+            json.get("c").and_then(|c| c.get("d")),
+            Some(&Json::Bool(true))
+        );
+        assert_eq!(json.get("e").and_then(Json::as_array), Some(&[][..]));
+        assert_eq!(json.get("missing"), None);
+        assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for src in [
+            "",
+            "[1,",
+            "[1 2]",
+            "[1,]",
+            "{\"a\" 1}",
+            "{a: 1}",
+            "{\"a\": 1,}",
+            "\"abc",
+            "\"\\x\"",
+            "\"\\u12\"",
+            "tru",
+            "-",
+            "1.2.3",
+            "1 2",
+            "@",
+        ] {
+            assert!(Json::parse(src).is_err(), "{:?} parsed", src);
+        }
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let err = Json::parse("[1,\n  x]").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+}
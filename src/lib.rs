@@ -0,0 +1,39 @@
+#![allow(dead_code, unused_imports)]
+#[cfg(feature = "app")]
+pub mod app;
+#[cfg(feature = "app")]
+pub mod assets;
+pub mod cooldown;
+pub use cooldown::*;
+pub mod slot_map;
+pub use slot_map::*;
+pub mod vec2;
+pub use vec2::*;
+pub mod node;
+pub use node::*;
+pub mod link;
+pub use link::*;
+pub mod terrain;
+pub use terrain::*;
+pub mod field;
+pub use field::*;
+pub mod constraint;
+pub use constraint::*;
+pub mod chain;
+pub use chain::*;
+pub mod world;
+pub use world::*;
+pub mod save;
+pub use save::*;
+pub mod json;
+pub use json::*;
+pub mod scene;
+pub use scene::*;
+pub mod integrator;
+pub use integrator::*;
+pub mod solver;
+pub use solver::*;
+pub mod hash_grid;
+pub use hash_grid::*;
+pub mod event;
+pub use event::*;
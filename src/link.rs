@@ -1,40 +1,135 @@
+use crate::{Handle, NodeId};
+
+pub type LinkId = Handle<Link>;
+
 #[repr(u32, align(4))]
 #[derive(Clone)]
 pub enum Link {
     Link {
-        n1: u32,
-        n2: u32,
+        n1: NodeId,
+        n2: NodeId,
         dist: f32,
+        strength: f32,
     },
     Rope {
-        n1: u32,
-        n2: u32,
+        n1: NodeId,
+        n2: NodeId,
         dist: f32,
+        strength: f32,
     },
+    /// Piston whose rest length moves at up to `speed` within `min..=max`
     Hydraulic {
-        n1: u32,
-        n2: u32,
+        n1: NodeId,
+        n2: NodeId,
         dist: f32,
         speed: f32,
+        strength: f32,
+        min: f32,
+        max: f32,
+        control: HydraulicControl,
     },
+    /// Hooke spring with a dashpot, `stiffness` in force per unit of stretch
+    /// and `damping` in force per unit of stretching speed
     Spring {
-        n1: u32,
-        n2: u32,
+        n1: NodeId,
+        n2: NodeId,
         dist: f32,
         stiffness: f32,
+        damping: f32,
+        strength: f32,
     },
 }
 
-impl Link {
-    pub fn default() -> Self {
+/// Periodic stroke shape, maps a cycle fraction to how far a piston is extended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wave {
+    Sine,
+    Square,
+    PingPong,
+}
+
+impl Wave {
+    /// Extension in 0..=1 at cycle fraction `t` in 0..1, starting retracted
+    pub fn sample(&self, t: f32) -> f32 {
+        match self {
+            Wave::Sine => 0.5 - 0.5 * (t * std::f32::consts::TAU).cos(),
+            Wave::Square => {
+                if t < 0.5 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Wave::PingPong => 1.0 - (2.0 * t - 1.0).abs(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HydraulicControl {
+    Hold,
+    Extend,
+    Retract,
+    /// Follows `wave` between the stroke limits, `period` in seconds and `phase` as a cycle fraction,
+    /// holds when either limit is unbounded
+    Pattern {
+        wave: Wave,
+        period: f32,
+        phase: f32,
+    },
+}
+
+impl HydraulicControl {
+    /// Rest length after `dt`, moving at most `speed * dt` and staying within `min..=max`,
+    /// `max` wins over inverted limits
+    pub fn advance(&self, dist: f32, min: f32, max: f32, speed: f32, time: f32, dt: f32) -> f32 {
+        let step = speed * dt;
+        let dist = match *self {
+            HydraulicControl::Hold => dist,
+            HydraulicControl::Extend => dist + step,
+            HydraulicControl::Retract => dist - step,
+            HydraulicControl::Pattern {
+                wave,
+                period,
+                phase,
+            } => {
+                // Patterns need a bounded stroke to sweep over
+                if period <= 0.0 || !(max - min).is_finite() {
+                    return dist.max(min).min(max);
+                }
+                let t = (time / period + phase).rem_euclid(1.0);
+                let target = min + (max - min) * wave.sample(t);
+                dist + (target - dist).max(-step.abs()).min(step.abs())
+            }
+        };
+        // Unlike clamp this can't panic on limits nobody checked
+        dist.max(min).min(max)
+    }
+}
+
+/// Whether `min..=max` can bound a stroke or travel, false for NaN or inverted limits
+/// and for ranges that lie entirely at infinity
+pub fn valid_limits(min: f32, max: f32) -> bool {
+    min <= max && min < f32::INFINITY && max > f32::NEG_INFINITY
+}
+
+impl Default for Link {
+    fn default() -> Self {
         Self::Link {
-            n1: 0,
-            n2: 0,
+            n1: NodeId::NULL,
+            n2: NodeId::NULL,
             dist: 0.0,
+            strength: Link::UNBREAKABLE,
         }
     }
+}
 
-    pub fn n1(&self) -> u32 {
+impl Link {
+    pub const UNBREAKABLE: f32 = f32::INFINITY;
+    /// Reference force for stress of unbreakable links
+    pub const NOMINAL_STRENGTH: f32 = 100.0;
+
+    pub fn n1(&self) -> NodeId {
         match self {
             Link::Link { n1, .. }
             | Link::Hydraulic { n1, .. }
@@ -43,7 +138,7 @@ impl Link {
         }
     }
 
-    pub fn n2(&self) -> u32 {
+    pub fn n2(&self) -> NodeId {
         match self {
             Link::Link { n2, .. }
             | Link::Hydraulic { n2, .. }
@@ -61,7 +156,21 @@ impl Link {
         }
     }
 
-    pub fn set_n1(&mut self, node1: u32) {
+    /// Largest force the link withstands before breaking, in tension or compression
+    pub fn strength(&self) -> f32 {
+        match self {
+            Link::Link { strength, .. }
+            | Link::Hydraulic { strength, .. }
+            | Link::Spring { strength, .. }
+            | Link::Rope { strength, .. } => *strength,
+        }
+    }
+
+    pub fn breakable(&self) -> bool {
+        self.strength() != Link::UNBREAKABLE
+    }
+
+    pub fn set_n1(&mut self, node1: NodeId) {
         match self {
             Link::Link { n1, .. }
             | Link::Hydraulic { n1, .. }
@@ -70,7 +179,7 @@ impl Link {
         }
     }
 
-    pub fn set_n2(&mut self, node2: u32) {
+    pub fn set_n2(&mut self, node2: NodeId) {
         match self {
             Link::Link { n2, .. }
             | Link::Hydraulic { n2, .. }
@@ -88,7 +197,23 @@ impl Link {
         }
     }
 
-    pub fn linked_to(&self, n: u32) -> bool {
+    pub fn set_strength(&mut self, max_force: f32) {
+        match self {
+            Link::Link { strength, .. }
+            | Link::Hydraulic { strength, .. }
+            | Link::Spring { strength, .. }
+            | Link::Rope { strength, .. } => *strength = max_force,
+        }
+    }
+
+    /// Changes how a hydraulic drives its length, other links ignore it
+    pub fn set_hydraulic_control(&mut self, new_control: HydraulicControl) {
+        if let Link::Hydraulic { control, .. } = self {
+            *control = new_control;
+        }
+    }
+
+    pub fn linked_to(&self, n: NodeId) -> bool {
         self.n1() == n || self.n2() == n
     }
 }
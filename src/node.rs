@@ -1,4 +1,6 @@
-use crate::Vec2;
+use crate::{Handle, Vec2};
+
+pub type NodeId = Handle<Node>;
 
 bitflags::bitflags! {
     #[derive(Default, Clone, Copy)]
@@ -15,12 +17,55 @@ impl From<Axes> for u32 {
     }
 }
 
+bitflags::bitflags! {
+    /// Collision layers, two bodies collide when each one's category is in the other's mask
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct Layers: u32 {
+        const DEFAULT = 1;
+        const ALL = u32::MAX;
+    }
+}
+
+impl Layers {
+    /// Single layer `i` in 0..32
+    pub fn group(i: u32) -> Self {
+        Self::from_bits_retain(1 << i)
+    }
+}
+
+/// Drives linked neighbours around the node towards a target angular velocity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Motor {
+    /// Target angular velocity in radians per second, positive is counter-clockwise
+    pub speed: f32,
+    /// Most torque the motor can apply, it stalls under heavier loads
+    pub max_torque: f32,
+    pub enabled: bool,
+}
+
+impl Default for Motor {
+    fn default() -> Self {
+        Self {
+            speed: 8.0,
+            max_torque: 4.0,
+            enabled: true,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Node {
     pub p: Vec2,
     pub v: Vec2,
     pub fixed_p: Vec2,
-    pub rotor_speed: f32,
+    pub motor: Option<Motor>,
+    pub mass: f32,
+    pub category: Layers,
+    pub mask: Layers,
+    /// Fraction of the approach speed kept after a collision, 1 is perfectly elastic
+    pub restitution: f32,
+    /// Coulomb friction coefficient against sliding along a contact
+    pub friction: f32,
 }
 
 impl Default for Node {
@@ -29,16 +74,22 @@ impl Default for Node {
             p: Vec2::ZERO,
             v: Vec2::ZERO,
             fixed_p: Vec2::splat(f32::MAX),
-            rotor_speed: 0.0,
+            motor: None,
+            mass: 1.0,
+            category: Layers::DEFAULT,
+            mask: Layers::ALL,
+            restitution: 1.0,
+            friction: 0.0,
         }
     }
 }
 
 impl Node {
     pub fn new(x: f32, y: f32) -> Self {
-        let mut node = Self::default();
-        node.p = Vec2::new(x, y);
-        node
+        Self {
+            p: Vec2::new(x, y),
+            ..Default::default()
+        }
     }
 
     pub fn new_fixed_x(x: f32, y: f32) -> Self {
@@ -59,10 +110,15 @@ impl Node {
         node
     }
 
-    pub fn new_rotor(x: f32, y: f32, speed: f32) -> Self {
+    pub fn new_motor(x: f32, y: f32, motor: Motor) -> Self {
+        let mut node = Self::new_fixed(x, y);
+        node.motor = Some(motor);
+        node
+    }
+
+    pub fn new_mass(x: f32, y: f32, mass: f32) -> Self {
         let mut node = Self::new(x, y);
-        node.fixed_p = node.p;
-        node.rotor_speed = speed;
+        node.mass = mass;
         node
     }
 
@@ -78,8 +134,8 @@ impl Node {
         self.fixed_x() && self.fixed_y()
     }
 
-    pub fn rotor(&self) -> bool {
-        self.rotor_speed != 0.0
+    pub fn motor(&self) -> bool {
+        self.motor.is_some()
     }
 
     pub fn move_by(&mut self, x: f32, y: f32) {
@@ -92,7 +148,43 @@ impl Node {
         }
     }
 
+    pub fn collides_with(&self, category: Layers, mask: Layers) -> bool {
+        self.category.intersects(mask) && category.intersects(self.mask)
+    }
+
+    /// Restitution and friction of a contact with another surface,
+    /// the less bouncy and the grippier of the two wins
+    pub fn contact_with(&self, restitution: f32, friction: f32) -> (f32, f32) {
+        (
+            self.restitution.min(restitution),
+            self.friction.max(friction),
+        )
+    }
+
+    pub fn inv_mass(&self) -> f32 {
+        1.0 / self.mass
+    }
+
+    /// Inverse mass links, collisions and constraints see, fixed nodes never give way
+    pub fn constraint_inv_mass(&self) -> f32 {
+        if self.fixed() {
+            0.0
+        } else {
+            self.inv_mass()
+        }
+    }
+
+    /// Shares of a correction between `self` and `other`, the lighter node moves more
+    /// and a fixed node not at all
+    pub fn mass_split(&self, other: &Node) -> (f32, f32) {
+        let (ia, ib) = (self.constraint_inv_mass(), other.constraint_inv_mass());
+        if ia + ib == 0.0 {
+            return (0.0, 0.0);
+        }
+        (ia / (ia + ib), ib / (ia + ib))
+    }
+
     pub fn kinetic_energy(&self) -> f32 {
-        0.5 * self.v.len2()
+        0.5 * self.mass * self.v.len2()
     }
 }
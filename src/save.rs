@@ -0,0 +1,1145 @@
+use crate::{
+    valid_dir, valid_limits, AdaptiveSubsteps, Constraint, ForceField, HydraulicControl,
+    IntegratorKind, Json, JsonError, Layers, Link, Motor, Node, Shape, SlotMap, Solver,
+    SolverOrder, Terrain, Vec2, Wave, World, Xpbd,
+};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+// Save file layout, all values little-endian:
+//   magic "SILK", format version u32
+//   radius f32, flags u8: bit 0 link collision, bit 1 skip linked collisions, bit 2 deterministic
+//   gravity.x gravity.y linear_drag quadratic_drag time (f32),
+//     solver tag u8: 0 impulse, 1 xpbd gauss-seidel, 2 xpbd jacobi,
+//     xpbd iterations u32 then link rope hydraulic compliance (f32),
+//     integrator u8: 0 euler, 2 verlet, 3 rk4, 1 from older files loads as euler,
+//     adaptive substepping u8: 0 off, 1 on then max substeps u32,
+//     max travel, max link error, max energy drift (f32)
+//   node count u32, nodes: p.x p.y v.x v.y fixed_p.x fixed_p.y (f32),
+//     motor flags u8: bit 0 present, bit 1 enabled, motor speed max_torque mass (f32), category mask (u32),
+//     restitution friction (f32)
+//   link count u32, links: n1 u32, n2 u32, dist f32, strength f32, tag u8, tag specific f32 params
+//     (hydraulic speed min max then control tag u8 period phase, spring stiffness and damping)
+//   terrain count u32, terrain: tag u8, restitution f32, friction f32, category u32, mask u32,
+//     point count u32, points (f32 pairs)
+//   field count u32, fields: tag u8, center.x center.y radius (f32), tag specific f32 params
+//   constraint count u32, constraints: tag u8, node u32, min max (f32),
+//     tag specific params (track origin.x origin.y dir.x dir.y f32, slider a b u32,
+//     angle a b u32 then angle stiffness f32, pulley a b u32 then length ratio f32,
+//     gear other u32 then ratio f32)
+//   chain count u32, chains: node count u32, node indices u32 from one end to the other
+// Version 0 files predate the header and start directly with the radius
+// Version 1 added the magic and version header in front of the same body
+// Version 2 added node mass
+// Version 3 added link strength, infinite for unbreakable links
+// Version 4 added terrain
+// Version 5 added the flags byte
+// Version 6 added collision layers to nodes and terrain
+// Version 7 added node restitution and friction
+// Version 8 added gravity, drag and force fields
+// Version 9 switched springs to physical stiffness and added damping
+// Version 10 added hydraulic stroke limits and control, and the world time
+// Version 11 replaced the rotor speed with motors
+// Version 12 added track and slider constraints
+// Version 13 added angle constraints
+// Version 14 added pulleys and gears
+// Version 15 added chains
+// Version 16 added the solver
+// Version 17 added the integrator
+// Version 18 added adaptive substepping
+// Version 19 added the deterministic flag
+pub const SAVE_MAGIC: [u8; 4] = *b"SILK";
+pub const SAVE_VERSION: u32 = 19;
+/// Physical stiffness matching a spring from before version 9 for unit masses
+pub const LEGACY_SPRING_STIFFNESS: f32 = 512.0;
+/// Motor target speed per unit of rotor speed from before version 11
+pub const LEGACY_ROTOR_SPEED: f32 = 8.0;
+const MOTOR_PRESENT: u8 = 1;
+const MOTOR_ENABLED: u8 = 2;
+const FLAG_LINK_COLLISION: u8 = 1;
+const FLAG_SKIP_LINKED_COLLISIONS: u8 = 2;
+const FLAG_DETERMINISTIC: u8 = 4;
+
+/// Motor replacing a rotor from before version 11, which only ever spun forwards
+pub fn legacy_motor(rotor_speed: f32) -> Option<Motor> {
+    (rotor_speed > 0.0).then(|| Motor {
+        speed: rotor_speed * LEGACY_ROTOR_SPEED,
+        ..Default::default()
+    })
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Truncated,
+    UnsupportedVersion(u32),
+    BadLinkTag {
+        link: u32,
+        tag: u8,
+    },
+    BadTerrainTag {
+        terrain: u32,
+        tag: u8,
+    },
+    BadFieldTag {
+        field: u32,
+        tag: u8,
+    },
+    BadConstraintTag {
+        constraint: u32,
+        tag: u8,
+    },
+    BadControlTag {
+        link: u32,
+        tag: u8,
+    },
+    BadSolverTag(u8),
+    BadIntegratorTag(u8),
+    /// Hydraulic stroke limits that are NaN or inverted
+    BadLinkLimits {
+        link: u32,
+        min: f32,
+        max: f32,
+    },
+    /// Travel or flex limits that are NaN or inverted
+    BadConstraintLimits {
+        constraint: u32,
+        min: f32,
+        max: f32,
+    },
+    /// Track direction that is zero or too large to normalise
+    BadTrackDir {
+        constraint: u32,
+        dir: Vec2,
+    },
+    /// Link joining a node to itself or two nodes another link already joins
+    InvalidLink(u32),
+    NodeOutOfRange {
+        link: u32,
+        node: u32,
+        nodes: u32,
+    },
+    ConstraintNodeOutOfRange {
+        constraint: u32,
+        node: u32,
+        nodes: u32,
+    },
+    /// Chain nodes out of range or not linked one after another
+    BadChain(u32),
+    Json(JsonError),
+    InvalidScene(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::Truncated => write!(f, "Save file is truncated"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "Save format version {} is newer than supported version {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::BadLinkTag { link, tag } => {
+                write!(f, "Link {} has unknown type tag {}", link, tag)
+            }
+            SaveError::BadTerrainTag { terrain, tag } => {
+                write!(f, "Terrain {} has unknown shape tag {}", terrain, tag)
+            }
+            SaveError::BadFieldTag { field, tag } => {
+                write!(f, "Force field {} has unknown type tag {}", field, tag)
+            }
+            SaveError::BadConstraintTag { constraint, tag } => {
+                write!(f, "Constraint {} has unknown type tag {}", constraint, tag)
+            }
+            SaveError::BadControlTag { link, tag } => {
+                write!(f, "Link {} has unknown hydraulic control tag {}", link, tag)
+            }
+            SaveError::BadSolverTag(tag) => write!(f, "Unknown solver tag {}", tag),
+            SaveError::BadIntegratorTag(tag) => write!(f, "Unknown integrator tag {}", tag),
+            SaveError::BadLinkLimits { link, min, max } => {
+                write!(f, "Link {} has invalid limits {} to {}", link, min, max)
+            }
+            SaveError::BadConstraintLimits {
+                constraint,
+                min,
+                max,
+            } => write!(
+                f,
+                "Constraint {} has invalid limits {} to {}",
+                constraint, min, max
+            ),
+            SaveError::BadTrackDir { constraint, dir } => write!(
+                f,
+                "Constraint {} has track direction ({}, {}) that is zero or not finite",
+                constraint, dir.x, dir.y
+            ),
+            SaveError::InvalidLink(link) => write!(
+                f,
+                "Link {} joins a node to itself or repeats another link",
+                link
+            ),
+            SaveError::NodeOutOfRange { link, node, nodes } => write!(
+                f,
+                "Link {} references node {} but the file only has {} nodes",
+                link, node, nodes
+            ),
+            SaveError::ConstraintNodeOutOfRange {
+                constraint,
+                node,
+                nodes,
+            } => write!(
+                f,
+                "Constraint {} references node {} but the file only has {} nodes",
+                constraint, node, nodes
+            ),
+            SaveError::BadChain(chain) => {
+                write!(f, "Chain {} does not run along linked nodes", chain)
+            }
+            SaveError::Json(err) => write!(f, "Invalid JSON: {}", err),
+            SaveError::InvalidScene(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io(err) => Some(err),
+            SaveError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            SaveError::Truncated
+        } else {
+            SaveError::Io(err)
+        }
+    }
+}
+
+impl From<JsonError> for SaveError {
+    fn from(err: JsonError) -> Self {
+        SaveError::Json(err)
+    }
+}
+
+/// On disk representation, picked from the file extension
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SaveFormat {
+    Binary,
+    Json,
+}
+
+impl SaveFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => SaveFormat::Json,
+            _ => SaveFormat::Binary,
+        }
+    }
+}
+
+struct SaveReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> SaveReader<R> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        let mut buf = [0u8; N];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+}
+
+impl World {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        let path = path.as_ref();
+        match SaveFormat::from_path(path) {
+            SaveFormat::Binary => Self::deserialize(&mut BufReader::new(fs::File::open(path)?)),
+            SaveFormat::Json => Self::from_json(&Json::parse(&fs::read_to_string(path)?)?),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let path = path.as_ref();
+        match SaveFormat::from_path(path) {
+            SaveFormat::Binary => {
+                let mut writer = BufWriter::new(fs::File::create(path)?);
+                self.serealize(&mut writer)?;
+                writer.flush()?;
+            }
+            SaveFormat::Json => fs::write(path, format!("{}\n", self.to_json()))?,
+        }
+        Ok(())
+    }
+
+    pub fn serealize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&SAVE_MAGIC)?;
+        writer.write_all(&SAVE_VERSION.to_le_bytes())?;
+        writer.write_all(&self.radius.to_le_bytes())?;
+        let mut flags = 0;
+        if self.link_collision {
+            flags |= FLAG_LINK_COLLISION;
+        }
+        if self.skip_linked_collisions {
+            flags |= FLAG_SKIP_LINKED_COLLISIONS;
+        }
+        if self.deterministic {
+            flags |= FLAG_DETERMINISTIC;
+        }
+        writer.write_all(&[flags])?;
+        writer.write_all(&self.gravity.x.to_le_bytes())?;
+        writer.write_all(&self.gravity.y.to_le_bytes())?;
+        writer.write_all(&self.linear_drag.to_le_bytes())?;
+        writer.write_all(&self.quadratic_drag.to_le_bytes())?;
+        writer.write_all(&self.time.to_le_bytes())?;
+        match self.solver {
+            Solver::Impulse => writer.write_all(&[0])?,
+            Solver::Xpbd(xpbd) => {
+                let tag = match xpbd.order {
+                    SolverOrder::GaussSeidel => 1,
+                    SolverOrder::Jacobi => 2,
+                };
+                writer.write_all(&[tag])?;
+                writer.write_all(&xpbd.iterations.to_le_bytes())?;
+                writer.write_all(&xpbd.link_compliance.to_le_bytes())?;
+                writer.write_all(&xpbd.rope_compliance.to_le_bytes())?;
+                writer.write_all(&xpbd.hydraulic_compliance.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&[self.integrator.tag()])?;
+        match self.adaptive {
+            Some(adaptive) => {
+                writer.write_all(&[1])?;
+                writer.write_all(&adaptive.max_substeps.to_le_bytes())?;
+                writer.write_all(&adaptive.max_travel.to_le_bytes())?;
+                writer.write_all(&adaptive.max_link_error.to_le_bytes())?;
+                writer.write_all(&adaptive.max_energy_drift.to_le_bytes())?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+
+        let mut node_indices = HashMap::new();
+        writer.write_all(&(self.nodes.len() as u32).to_le_bytes())?;
+        for (i, (id, n)) in self.nodes.iter().enumerate() {
+            node_indices.insert(id, i as u32);
+            writer.write_all(&n.p.x.to_le_bytes())?;
+            writer.write_all(&n.p.y.to_le_bytes())?;
+            writer.write_all(&n.v.x.to_le_bytes())?;
+            writer.write_all(&n.v.y.to_le_bytes())?;
+            writer.write_all(&n.fixed_p.x.to_le_bytes())?;
+            writer.write_all(&n.fixed_p.y.to_le_bytes())?;
+            let motor = n.motor.unwrap_or_default();
+            let mut motor_flags = 0;
+            if n.motor() {
+                motor_flags |= MOTOR_PRESENT;
+            }
+            if motor.enabled {
+                motor_flags |= MOTOR_ENABLED;
+            }
+            writer.write_all(&[motor_flags])?;
+            writer.write_all(&motor.speed.to_le_bytes())?;
+            writer.write_all(&motor.max_torque.to_le_bytes())?;
+            writer.write_all(&n.mass.to_le_bytes())?;
+            writer.write_all(&n.category.bits().to_le_bytes())?;
+            writer.write_all(&n.mask.bits().to_le_bytes())?;
+            writer.write_all(&n.restitution.to_le_bytes())?;
+            writer.write_all(&n.friction.to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.links.len() as u32).to_le_bytes())?;
+        for l in self.links.values() {
+            writer.write_all(&node_indices[&l.n1()].to_le_bytes())?;
+            writer.write_all(&node_indices[&l.n2()].to_le_bytes())?;
+            writer.write_all(&l.dist().to_le_bytes())?;
+            writer.write_all(&l.strength().to_le_bytes())?;
+            match *l {
+                Link::Link { .. } => {
+                    writer.write_all(&[0])?;
+                }
+                Link::Rope { .. } => {
+                    writer.write_all(&[1])?;
+                }
+                Link::Hydraulic {
+                    speed,
+                    min,
+                    max,
+                    control,
+                    ..
+                } => {
+                    writer.write_all(&[2])?;
+                    writer.write_all(&speed.to_le_bytes())?;
+                    writer.write_all(&min.to_le_bytes())?;
+                    writer.write_all(&max.to_le_bytes())?;
+                    let (tag, period, phase) = match control {
+                        HydraulicControl::Hold => (0, 0.0, 0.0),
+                        HydraulicControl::Extend => (1, 0.0, 0.0),
+                        HydraulicControl::Retract => (2, 0.0, 0.0),
+                        HydraulicControl::Pattern {
+                            wave,
+                            period,
+                            phase,
+                        } => {
+                            let tag = match wave {
+                                Wave::Sine => 3,
+                                Wave::Square => 4,
+                                Wave::PingPong => 5,
+                            };
+                            (tag, period, phase)
+                        }
+                    };
+                    writer.write_all(&[tag])?;
+                    writer.write_all(&period.to_le_bytes())?;
+                    writer.write_all(&phase.to_le_bytes())?;
+                }
+                Link::Spring {
+                    stiffness, damping, ..
+                } => {
+                    writer.write_all(&[3])?;
+                    writer.write_all(&stiffness.to_le_bytes())?;
+                    writer.write_all(&damping.to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.write_all(&(self.terrain.len() as u32).to_le_bytes())?;
+        for t in self.terrain.values() {
+            let (tag, points) = match &t.shape {
+                Shape::Segment { a, b } => (0, vec![*a, *b]),
+                Shape::Polyline { points } => (1, points.clone()),
+                Shape::Polygon { points } => (2, points.clone()),
+            };
+            writer.write_all(&[tag])?;
+            writer.write_all(&t.restitution.to_le_bytes())?;
+            writer.write_all(&t.friction.to_le_bytes())?;
+            writer.write_all(&t.category.bits().to_le_bytes())?;
+            writer.write_all(&t.mask.bits().to_le_bytes())?;
+            writer.write_all(&(points.len() as u32).to_le_bytes())?;
+            for p in points {
+                writer.write_all(&p.x.to_le_bytes())?;
+                writer.write_all(&p.y.to_le_bytes())?;
+            }
+        }
+
+        writer.write_all(&(self.fields.len() as u32).to_le_bytes())?;
+        for f in self.fields.values() {
+            let (tag, params) = match *f {
+                ForceField::Uniform { force, .. } => (0, vec![force.x, force.y]),
+                ForceField::Attractor { strength, .. } => (1, vec![strength]),
+                ForceField::Vortex { strength, .. } => (2, vec![strength]),
+            };
+            writer.write_all(&[tag])?;
+            writer.write_all(&f.center().x.to_le_bytes())?;
+            writer.write_all(&f.center().y.to_le_bytes())?;
+            writer.write_all(&f.radius().to_le_bytes())?;
+            for param in params {
+                writer.write_all(&param.to_le_bytes())?;
+            }
+        }
+
+        writer.write_all(&(self.constraints.len() as u32).to_le_bytes())?;
+        for c in self.constraints.values() {
+            let tag = match c {
+                Constraint::Track { .. } => 0,
+                Constraint::Slider { .. } => 1,
+                Constraint::Angle { .. } => 2,
+                Constraint::Pulley { .. } => 3,
+                Constraint::Gear { .. } => 4,
+            };
+            let (min, max) = c.limits();
+            writer.write_all(&[tag])?;
+            writer.write_all(&node_indices[&c.node()].to_le_bytes())?;
+            writer.write_all(&min.to_le_bytes())?;
+            writer.write_all(&max.to_le_bytes())?;
+            match *c {
+                Constraint::Track { origin, dir, .. } => {
+                    for param in [origin.x, origin.y, dir.x, dir.y] {
+                        writer.write_all(&param.to_le_bytes())?;
+                    }
+                }
+                Constraint::Slider { a, b, .. } => {
+                    writer.write_all(&node_indices[&a].to_le_bytes())?;
+                    writer.write_all(&node_indices[&b].to_le_bytes())?;
+                }
+                Constraint::Angle {
+                    a,
+                    b,
+                    angle,
+                    stiffness,
+                    ..
+                } => {
+                    writer.write_all(&node_indices[&a].to_le_bytes())?;
+                    writer.write_all(&node_indices[&b].to_le_bytes())?;
+                    writer.write_all(&angle.to_le_bytes())?;
+                    writer.write_all(&stiffness.to_le_bytes())?;
+                }
+                Constraint::Pulley {
+                    a,
+                    b,
+                    length,
+                    ratio,
+                    ..
+                } => {
+                    writer.write_all(&node_indices[&a].to_le_bytes())?;
+                    writer.write_all(&node_indices[&b].to_le_bytes())?;
+                    writer.write_all(&length.to_le_bytes())?;
+                    writer.write_all(&ratio.to_le_bytes())?;
+                }
+                Constraint::Gear { other, ratio, .. } => {
+                    writer.write_all(&node_indices[&other].to_le_bytes())?;
+                    writer.write_all(&ratio.to_le_bytes())?;
+                }
+            }
+        }
+
+        writer.write_all(&(self.chains.len() as u32).to_le_bytes())?;
+        for chain in self.chains.values() {
+            writer.write_all(&(chain.nodes.len() as u32).to_le_bytes())?;
+            for n in chain.nodes.iter() {
+                writer.write_all(&node_indices[n].to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads any save format version up to `SAVE_VERSION`, including unversioned files
+    pub fn deserialize<R: Read>(reader: &mut R) -> Result<Self, SaveError> {
+        let mut reader = SaveReader { reader };
+
+        // Unversioned files start with the radius instead of the magic
+        let head = reader.bytes::<4>()?;
+        let (version, radius) = if head == SAVE_MAGIC {
+            let version = reader.u32()?;
+            if version > SAVE_VERSION {
+                return Err(SaveError::UnsupportedVersion(version));
+            }
+            (version, reader.f32()?)
+        } else {
+            (0, f32::from_le_bytes(head))
+        };
+        Self::read_body(&mut reader, version, radius)
+    }
+
+    fn read_body<R: Read>(
+        reader: &mut SaveReader<R>,
+        version: u32,
+        radius: f32,
+    ) -> Result<Self, SaveError> {
+        let flags = if version >= 5 { reader.u8()? } else { 0 };
+        let defaults = Self::default();
+        let (gravity, linear_drag, quadratic_drag) = if version >= 8 {
+            (
+                Vec2::new(reader.f32()?, reader.f32()?),
+                reader.f32()?,
+                reader.f32()?,
+            )
+        } else {
+            (
+                defaults.gravity,
+                defaults.linear_drag,
+                defaults.quadratic_drag,
+            )
+        };
+        let time = if version >= 10 { reader.f32()? } else { 0.0 };
+        let solver = if version >= 16 {
+            let order = match reader.u8()? {
+                0 => None,
+                1 => Some(SolverOrder::GaussSeidel),
+                2 => Some(SolverOrder::Jacobi),
+                tag => return Err(SaveError::BadSolverTag(tag)),
+            };
+            match order {
+                Some(order) => Solver::Xpbd(Xpbd {
+                    iterations: reader.u32()?,
+                    order,
+                    link_compliance: reader.f32()?,
+                    rope_compliance: reader.f32()?,
+                    hydraulic_compliance: reader.f32()?,
+                }),
+                None => Solver::Impulse,
+            }
+        } else {
+            Solver::Impulse
+        };
+        let integrator = if version >= 17 {
+            let tag = reader.u8()?;
+            IntegratorKind::from_tag(tag).ok_or(SaveError::BadIntegratorTag(tag))?
+        } else {
+            IntegratorKind::Euler
+        };
+        let adaptive = if version >= 18 && reader.u8()? != 0 {
+            Some(AdaptiveSubsteps {
+                max_substeps: reader.u32()?,
+                max_travel: reader.f32()?,
+                max_link_error: reader.f32()?,
+                max_energy_drift: reader.f32()?,
+            })
+        } else {
+            None
+        };
+        let nodes_len = reader.u32()?;
+        let mut nodes = SlotMap::new();
+        let mut node_ids = Vec::new();
+        for i in 0..nodes_len {
+            let mut node = Node::default();
+            node.p.x = reader.f32()?;
+            node.p.y = reader.f32()?;
+            node.v.x = reader.f32()?;
+            node.v.y = reader.f32()?;
+            node.fixed_p.x = reader.f32()?;
+            node.fixed_p.y = reader.f32()?;
+            if version >= 11 {
+                let motor_flags = reader.u8()?;
+                let motor = Motor {
+                    speed: reader.f32()?,
+                    max_torque: reader.f32()?,
+                    enabled: motor_flags & MOTOR_ENABLED != 0,
+                };
+                if motor_flags & MOTOR_PRESENT != 0 {
+                    if motor.max_torque.is_nan() || motor.max_torque <= 0.0 {
+                        return Err(SaveError::InvalidScene(format!(
+                            "Node {} motor \"max_torque\" must be positive",
+                            i
+                        )));
+                    }
+                    node.motor = Some(motor);
+                }
+            } else {
+                node.motor = legacy_motor(reader.f32()?);
+            }
+            if version >= 2 {
+                node.mass = reader.f32()?;
+                if node.mass.is_nan() || node.mass <= 0.0 {
+                    return Err(SaveError::InvalidScene(format!(
+                        "Node {} \"mass\" must be positive",
+                        i
+                    )));
+                }
+            }
+            if version >= 6 {
+                node.category = Layers::from_bits_retain(reader.u32()?);
+                node.mask = Layers::from_bits_retain(reader.u32()?);
+            }
+            if version >= 7 {
+                node.restitution = reader.f32()?;
+                node.friction = reader.f32()?;
+            }
+            node_ids.push(nodes.insert(node));
+        }
+
+        let mut world = Self {
+            nodes,
+            radius,
+            link_collision: flags & FLAG_LINK_COLLISION != 0,
+            skip_linked_collisions: flags & FLAG_SKIP_LINKED_COLLISIONS != 0,
+            deterministic: flags & FLAG_DETERMINISTIC != 0,
+            gravity,
+            linear_drag,
+            quadratic_drag,
+            time,
+            solver,
+            integrator,
+            adaptive,
+            ..defaults
+        };
+
+        let links_len = reader.u32()?;
+        for i in 0..links_len {
+            let mut node_id = || -> Result<_, SaveError> {
+                let node = reader.u32()?;
+                node_ids
+                    .get(node as usize)
+                    .copied()
+                    .ok_or(SaveError::NodeOutOfRange {
+                        link: i,
+                        node,
+                        nodes: nodes_len,
+                    })
+            };
+            let (n1, n2) = (node_id()?, node_id()?);
+            let dist = reader.f32()?;
+            let strength = if version >= 3 {
+                reader.f32()?
+            } else {
+                Link::UNBREAKABLE
+            };
+
+            let link = match reader.u8()? {
+                0 => Link::Link {
+                    n1,
+                    n2,
+                    dist,
+                    strength,
+                },
+                1 => Link::Rope {
+                    n1,
+                    n2,
+                    dist,
+                    strength,
+                },
+                2 if version >= 10 => {
+                    let speed = reader.f32()?;
+                    let (min, max) = (reader.f32()?, reader.f32()?);
+                    if !valid_limits(min, max) {
+                        return Err(SaveError::BadLinkLimits { link: i, min, max });
+                    }
+                    let tag = reader.u8()?;
+                    let (period, phase) = (reader.f32()?, reader.f32()?);
+                    let control = match tag {
+                        0 => HydraulicControl::Hold,
+                        1 => HydraulicControl::Extend,
+                        2 => HydraulicControl::Retract,
+                        3..=5 => HydraulicControl::Pattern {
+                            wave: [Wave::Sine, Wave::Square, Wave::PingPong][tag as usize - 3],
+                            period,
+                            phase,
+                        },
+                        tag => return Err(SaveError::BadControlTag { link: i, tag }),
+                    };
+                    Link::Hydraulic {
+                        n1,
+                        n2,
+                        dist,
+                        speed,
+                        strength,
+                        min,
+                        max,
+                        control,
+                    }
+                }
+                // Older hydraulics extended without limit
+                2 => Link::Hydraulic {
+                    n1,
+                    n2,
+                    dist,
+                    speed: reader.f32()?,
+                    strength,
+                    min: 0.0,
+                    max: f32::INFINITY,
+                    control: HydraulicControl::Extend,
+                },
+                3 if version >= 9 => Link::Spring {
+                    n1,
+                    n2,
+                    dist,
+                    stiffness: reader.f32()?,
+                    damping: reader.f32()?,
+                    strength,
+                },
+                3 => Link::Spring {
+                    n1,
+                    n2,
+                    dist,
+                    stiffness: reader.f32()? * LEGACY_SPRING_STIFFNESS,
+                    damping: 0.0,
+                    strength,
+                },
+                tag => return Err(SaveError::BadLinkTag { link: i, tag }),
+            };
+            world.link_node(link).ok_or(SaveError::InvalidLink(i))?;
+        }
+
+        if version >= 4 {
+            for i in 0..reader.u32()? {
+                let tag = reader.u8()?;
+                let restitution = reader.f32()?;
+                let friction = reader.f32()?;
+                let (category, mask) = if version >= 6 {
+                    (
+                        Layers::from_bits_retain(reader.u32()?),
+                        Layers::from_bits_retain(reader.u32()?),
+                    )
+                } else {
+                    (Layers::DEFAULT, Layers::ALL)
+                };
+                let mut points = Vec::new();
+                for _ in 0..reader.u32()? {
+                    points.push(Vec2::new(reader.f32()?, reader.f32()?));
+                }
+                let shape = match (tag, points.as_slice()) {
+                    (0, &[a, b]) => Shape::Segment { a, b },
+                    (1, _) => Shape::Polyline { points },
+                    (2, _) => Shape::Polygon { points },
+                    (tag, _) => return Err(SaveError::BadTerrainTag { terrain: i, tag }),
+                };
+                world.add_terrain(Terrain {
+                    shape,
+                    restitution,
+                    friction,
+                    category,
+                    mask,
+                });
+            }
+        }
+
+        if version >= 8 {
+            for i in 0..reader.u32()? {
+                let tag = reader.u8()?;
+                let center = Vec2::new(reader.f32()?, reader.f32()?);
+                let radius = reader.f32()?;
+                let field = match tag {
+                    0 => ForceField::Uniform {
+                        center,
+                        radius,
+                        force: Vec2::new(reader.f32()?, reader.f32()?),
+                    },
+                    1 => ForceField::Attractor {
+                        center,
+                        radius,
+                        strength: reader.f32()?,
+                    },
+                    2 => ForceField::Vortex {
+                        center,
+                        radius,
+                        strength: reader.f32()?,
+                    },
+                    tag => return Err(SaveError::BadFieldTag { field: i, tag }),
+                };
+                world.add_field(field);
+            }
+        }
+
+        if version >= 12 {
+            for i in 0..reader.u32()? {
+                let node_id = |reader: &mut SaveReader<R>| -> Result<_, SaveError> {
+                    let node = reader.u32()?;
+                    node_ids.get(node as usize).copied().ok_or(
+                        SaveError::ConstraintNodeOutOfRange {
+                            constraint: i,
+                            node,
+                            nodes: nodes_len,
+                        },
+                    )
+                };
+                let tag = reader.u8()?;
+                let node = node_id(reader)?;
+                let (min, max) = (reader.f32()?, reader.f32()?);
+                if !valid_limits(min, max) {
+                    return Err(SaveError::BadConstraintLimits {
+                        constraint: i,
+                        min,
+                        max,
+                    });
+                }
+                let constraint = match tag {
+                    0 => {
+                        let origin = Vec2::new(reader.f32()?, reader.f32()?);
+                        let dir = Vec2::new(reader.f32()?, reader.f32()?);
+                        if !valid_dir(dir) {
+                            return Err(SaveError::BadTrackDir { constraint: i, dir });
+                        }
+                        Constraint::Track {
+                            node,
+                            origin,
+                            dir: dir.norm(),
+                            min,
+                            max,
+                        }
+                    }
+                    1 => Constraint::Slider {
+                        node,
+                        a: node_id(reader)?,
+                        b: node_id(reader)?,
+                        min,
+                        max,
+                    },
+                    2 => Constraint::Angle {
+                        node,
+                        a: node_id(reader)?,
+                        b: node_id(reader)?,
+                        angle: reader.f32()?,
+                        stiffness: reader.f32()?,
+                        min,
+                        max,
+                    },
+                    3 => Constraint::Pulley {
+                        node,
+                        a: node_id(reader)?,
+                        b: node_id(reader)?,
+                        length: reader.f32()?,
+                        ratio: reader.f32()?,
+                    },
+                    4 => Constraint::Gear {
+                        node,
+                        other: node_id(reader)?,
+                        ratio: reader.f32()?,
+                    },
+                    tag => return Err(SaveError::BadConstraintTag { constraint: i, tag }),
+                };
+                world.add_constraint(constraint);
+            }
+        }
+
+        if version >= 15 {
+            for i in 0..reader.u32()? {
+                let mut nodes = Vec::new();
+                for _ in 0..reader.u32()? {
+                    let node = reader.u32()?;
+                    nodes.push(*node_ids.get(node as usize).ok_or(SaveError::BadChain(i))?);
+                }
+                world.adopt_chain(nodes).ok_or(SaveError::BadChain(i))?;
+            }
+        }
+
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A world using every kind of object, stepped a little so values aren't round numbers
+    fn scene() -> World {
+        let mut world = World {
+            solver: Solver::Xpbd(Xpbd::default()),
+            integrator: IntegratorKind::Verlet,
+            adaptive: Some(AdaptiveSubsteps::default()),
+            link_collision: true,
+            deterministic: true,
+            ..Default::default()
+        };
+        let pin = world.add(Node::new_fixed(0.0, 0.0));
+        let a = world.add(Node::new_mass(0.3, 0.1, 2.5));
+        let b = world.add(Node::new_motor(0.6, 0.0, Motor::default()));
+        let c = world.add(Node::new_fixed_y(0.9, -0.2));
+        world.link_node(Link::Link {
+            n1: pin,
+            n2: a,
+            dist: 0.3,
+            strength: Link::NOMINAL_STRENGTH,
+        });
+        world.link_node(Link::Hydraulic {
+            n1: a,
+            n2: b,
+            dist: 0.3,
+            speed: 0.5,
+            strength: Link::UNBREAKABLE,
+            min: 0.2,
+            max: 0.4,
+            control: HydraulicControl::Pattern {
+                wave: Wave::Sine,
+                period: 2.0,
+                phase: 0.25,
+            },
+        });
+        world.link_node(Link::Spring {
+            n1: b,
+            n2: c,
+            dist: 0.35,
+            stiffness: 200.0,
+            damping: 1.5,
+            strength: Link::UNBREAKABLE,
+        });
+        world.add_chain(pin, c, 4, 1.2);
+        world.add_terrain(Terrain {
+            shape: Shape::Polyline {
+                points: vec![Vec2::new(-1.0, -0.5), Vec2::new(1.0, -0.6)],
+            },
+            ..Default::default()
+        });
+        world.add_field(ForceField::Vortex {
+            center: Vec2::new(0.5, 0.5),
+            radius: 0.4,
+            strength: 2.0,
+        });
+        world.add_constraint(Constraint::track(
+            c,
+            Vec2::new(0.0, -0.2),
+            Vec2::new(1.0, -0.2),
+        ));
+        let mut integrator = world.integrator;
+        for _ in 0..20 {
+            world.update(&mut integrator, 1.0 / 256.0, 1);
+        }
+        world
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let world = scene();
+        let mut bytes = Vec::new();
+        world.serealize(&mut bytes).unwrap();
+        let loaded = World::deserialize(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.state_hash(), world.state_hash());
+        assert_eq!(loaded.to_json(), world.to_json());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let world = scene();
+        let text = world.to_json().to_string();
+        let loaded = World::from_json(&Json::parse(&text).unwrap()).unwrap();
+        assert_eq!(loaded.state_hash(), world.state_hash());
+        assert_eq!(loaded.to_json(), world.to_json());
+        assert_eq!(loaded.chains.len(), 1);
+        assert_eq!(loaded.constraints.len(), 1);
+
+        // Unbounded limits have no JSON number and must survive as missing keys or null
+        let inf = f32::INFINITY;
+        for limits in [(-0.5, 0.6), (-inf, 0.6), (-0.5, inf), (-inf, inf)] {
+            let mut world = scene();
+            for link in world.links.values_mut() {
+                if let Link::Hydraulic { min, max, .. } = link {
+                    (*min, *max) = limits;
+                }
+            }
+            for constraint in world.constraints.values_mut() {
+                constraint.set_limits(limits.0, limits.1);
+            }
+            let text = world.to_json().to_string();
+            let loaded = World::from_json(&Json::parse(&text).unwrap()).unwrap();
+            let hydraulic = loaded.links.values().find_map(|l| match *l {
+                Link::Hydraulic { min, max, .. } => Some((min, max)),
+                _ => None,
+            });
+            assert_eq!(hydraulic, Some(limits));
+            let constraint = loaded.constraints.values().next().unwrap();
+            assert_eq!(constraint.limits(), limits);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_track_dirs() {
+        for bad in [Vec2::new(0.0, 0.0), Vec2::new(1e30, 0.0)] {
+            let mut world = scene();
+            for constraint in world.constraints.values_mut() {
+                if let Constraint::Track { dir, .. } = constraint {
+                    *dir = bad;
+                }
+            }
+            let mut bytes = Vec::new();
+            world.serealize(&mut bytes).unwrap();
+            let err = World::deserialize(&mut bytes.as_slice()).err();
+            assert!(matches!(
+                err,
+                Some(SaveError::BadTrackDir { constraint: 0, .. })
+            ));
+            let json = Json::parse(&world.to_json().to_string()).unwrap();
+            let err = World::from_json(&json).err();
+            assert!(matches!(
+                err,
+                Some(SaveError::BadTrackDir { constraint: 0, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_motors_without_torque() {
+        let rejected = |err: Option<SaveError>| matches!(err, Some(SaveError::InvalidScene(message)) if message.contains("max_torque"));
+        for bad in [0.0, -1.0, f32::NAN] {
+            let mut world = scene();
+            for motor in world.nodes.values_mut().filter_map(|n| n.motor.as_mut()) {
+                motor.max_torque = bad;
+            }
+            let mut bytes = Vec::new();
+            world.serealize(&mut bytes).unwrap();
+            assert!(rejected(World::deserialize(&mut bytes.as_slice()).err()));
+            // JSON has no NaN, which writes as null and fails as a missing number instead
+            if !bad.is_nan() {
+                let json = Json::parse(&world.to_json().to_string()).unwrap();
+                assert!(rejected(World::from_json(&json).err()));
+            }
+        }
+    }
+
+    // Body shared by version 0 and 1 files: nodes with a rotor speed, links without strength
+    fn legacy_body() -> Vec<u8> {
+        let mut body = Vec::new();
+        let put = |body: &mut Vec<u8>, v: f32| body.extend_from_slice(&v.to_le_bytes());
+        put(&mut body, 0.05);
+        body.extend_from_slice(&3u32.to_le_bytes());
+        let free = f32::MAX;
+        for node in [
+            [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.5, 0.0, free, free, 2.0],
+            [1.0, 1.0, 0.0, 0.0, free, free, 0.0],
+        ] {
+            node.into_iter().for_each(|v| put(&mut body, v));
+        }
+        body.extend_from_slice(&2u32.to_le_bytes());
+        // A hydraulic with its speed, then a spring with its old unitless stiffness
+        for (n1, n2, tag, param) in [(0u32, 1u32, 2u8, 0.3), (1, 2, 3, 0.5)] {
+            body.extend_from_slice(&n1.to_le_bytes());
+            body.extend_from_slice(&n2.to_le_bytes());
+            put(&mut body, 1.0);
+            body.push(tag);
+            put(&mut body, param);
+        }
+        body
+    }
+
+    #[test]
+    fn loads_legacy_files() {
+        let v0 = legacy_body();
+        let mut v1 = SAVE_MAGIC.to_vec();
+        v1.extend_from_slice(&1u32.to_le_bytes());
+        v1.extend_from_slice(&v0);
+        for bytes in [v0, v1] {
+            let world = World::deserialize(&mut bytes.as_slice()).unwrap();
+            assert_eq!(world.radius, 0.05);
+            let gravity = World::default().gravity;
+            assert_eq!((world.gravity.x, world.gravity.y), (gravity.x, gravity.y));
+            let nodes: Vec<&Node> = world.nodes.values().collect();
+            assert!(nodes[0].fixed() && nodes[0].motor.is_none());
+            assert_eq!((nodes[1].v.x, nodes[1].v.y), (0.5, 0.0));
+            assert_eq!(nodes[1].mass, 1.0);
+            let motor = nodes[1].motor.unwrap();
+            assert_eq!(motor.speed, 2.0 * LEGACY_ROTOR_SPEED);
+            assert!(motor.enabled);
+            assert!(nodes[2].motor.is_none());
+
+            let links: Vec<&Link> = world.links.values().collect();
+            assert!(matches!(
+                *links[0],
+                Link::Hydraulic {
+                    speed: 0.3,
+                    min: 0.0,
+                    max: f32::INFINITY,
+                    control: HydraulicControl::Extend,
+                    strength: Link::UNBREAKABLE,
+                    ..
+                }
+            ));
+            assert!(matches!(
+                *links[1],
+                Link::Spring {
+                    stiffness,
+                    damping: 0.0,
+                    ..
+                } if stiffness == 0.5 * LEGACY_SPRING_STIFFNESS
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_repeated_links() {
+        let mut bytes = legacy_body();
+        // Point the spring at the hydraulic's nodes
+        let spring_n1 = bytes.len() - (4 + 4 + 4 + 1 + 4);
+        bytes[spring_n1..spring_n1 + 8].copy_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0]);
+        let err = World::deserialize(&mut bytes.as_slice()).err();
+        assert!(matches!(err, Some(SaveError::InvalidLink(1))));
+    }
+}
@@ -0,0 +1,843 @@
+use crate::{
+    legacy_motor, valid_dir, valid_limits, AdaptiveSubsteps, Constraint, ForceField,
+    HydraulicControl, IntegratorKind, Json, Layers, Link, Motor, Node, NodeId, SaveError, Shape,
+    Solver, SolverOrder, Terrain, Vec2, Wave, World, Xpbd, LEGACY_SPRING_STIFFNESS, SAVE_VERSION,
+};
+use std::collections::HashMap;
+
+// Text scene layout, node indices in links refer to the order of "nodes":
+// {
+//   "version": 19,
+//   "radius": 0.05,
+//   "link_collision": false,
+//   "skip_linked_collisions": false,
+//   "deterministic": false,
+//   "gravity": [x, y], "linear_drag": k, "quadratic_drag": k, "time": t,
+//   "solver": { "type": "impulse" | "xpbd", "iterations": n, "order": "gauss_seidel" | "jacobi",
+//               "link_compliance": c, "rope_compliance": c, "hydraulic_compliance": c },
+//   "integrator": "euler" | "verlet" | "rk4",
+//   "adaptive": { "max_substeps": n, "max_travel": r, "max_link_error": e, "max_energy_drift": e },
+//   "nodes": [{ "p": [x, y], "v": [x, y], "fixed_x": x, "fixed_y": y,
+//               "motor": { "speed": w, "max_torque": t, "enabled": true }, "mass": m,
+//               "category": bits, "mask": bits, "restitution": e, "friction": f }],
+//   "links": [{ "type": "link" | "rope" | "hydraulic" | "spring", "n1": i, "n2": j, "dist": d }],
+//   "terrain": [{ "type": "segment" | "polyline" | "polygon", "points": [[x, y]],
+//                 "restitution": e, "friction": f, "category": bits, "mask": bits }],
+//   "fields": [{ "type": "uniform" | "attractor" | "vortex", "center": [x, y], "radius": r }],
+//   "constraints": [{ "type": "track", "node": i, "origin": [x, y], "dir": [x, y] }
+//                   | { "type": "slider", "node": i, "a": j, "b": k }
+//                   | { "type": "angle", "node": i, "a": j, "b": k, "angle": radians, "stiffness": s }
+//                   | { "type": "pulley", "node": i, "a": j, "b": k, "length": l, "ratio": r }
+//                   | { "type": "gear", "node": i, "other": j, "ratio": r }],
+//   "chains": [[i, j, k]]
+// }
+// "version" is the binary SAVE_VERSION the scene was written with, scenes without it count as current
+// "v", pins, "motor" and "mass" (default 1) are optional, "rotor_speed" from older scenes becomes a motor,
+// hydraulics carry "speed" and springs "stiffness" plus an optional "damping", springs in scenes before version 9 use the old stiffness scale
+// Links may set "strength", the force they break at, and are unbreakable without it
+// The collision switches and "deterministic" default to false, "terrain" is optional, a segment has exactly two points and polygons must be convex
+// Collision "category" defaults to 1 and "mask" to every layer,
+// node "restitution" defaults to 1 (elastic) and "friction" to 0
+// Hydraulics may limit their stroke with "min" (default 0, null for unbounded) and "max" (default unbounded)
+// and set "control" to "hold" | "extend" | "retract" | "sine" | "square" | "ping_pong", patterns take a "period" in seconds
+// and an optional "phase", hydraulics without "control" keep extending like they used to
+// Gravity defaults to [0, -6] and drag to 0, uniform fields carry "force": [x, y] and the others
+// "strength", a field without "radius" covers the whole world
+// Constraints may limit travel along their line with "min" and "max", sliders measure from "a" towards "b"
+// Angles without "stiffness" are rigid welds, their "min" and "max" limit flexing around "angle"
+// A pulley "ratio" defaults to 1, gears always need one
+// Scenes without "solver" use the impulse solver, missing xpbd settings take their defaults,
+// the "integrator" defaults to euler, without "adaptive" ticks take the substeps they are given
+// and missing adaptive tolerances take their defaults
+// A chain lists its nodes from one end to the other, each joined to the next by one of the "links"
+
+fn vec2_json(v: Vec2) -> Json {
+    Json::Array(vec![v.x.into(), v.y.into()])
+}
+
+fn invalid(what: impl Into<String>) -> SaveError {
+    SaveError::InvalidScene(what.into())
+}
+
+fn field<'a>(json: &'a Json, key: &str, what: &str) -> Result<&'a Json, SaveError> {
+    json.get(key)
+        .ok_or_else(|| invalid(format!("{} is missing \"{}\"", what, key)))
+}
+
+fn f32_field(json: &Json, key: &str, what: &str) -> Result<f32, SaveError> {
+    field(json, key, what)?
+        .as_f32()
+        .ok_or_else(|| invalid(format!("{} \"{}\" is not a number", what, key)))
+}
+
+fn opt_f32_field(json: &Json, key: &str, what: &str) -> Result<Option<f32>, SaveError> {
+    match json.get(key) {
+        None | Some(Json::Null) => Ok(None),
+        Some(_) => f32_field(json, key, what).map(Some),
+    }
+}
+
+fn opt_bool_field(json: &Json, key: &str, what: &str) -> Result<Option<bool>, SaveError> {
+    match json.get(key) {
+        None | Some(Json::Null) => Ok(None),
+        Some(v) => v
+            .as_bool()
+            .map(Some)
+            .ok_or_else(|| invalid(format!("{} \"{}\" is not a boolean", what, key))),
+    }
+}
+
+fn layers_field(json: &Json, key: &str, what: &str, default: Layers) -> Result<Layers, SaveError> {
+    match json.get(key) {
+        None | Some(Json::Null) => Ok(default),
+        Some(v) => v
+            .as_u32()
+            .map(Layers::from_bits_retain)
+            .ok_or_else(|| invalid(format!("{} \"{}\" is not a layer bit set", what, key))),
+    }
+}
+
+fn push_layers(fields: &mut Vec<(String, Json)>, category: Layers, mask: Layers) {
+    if category != Layers::DEFAULT {
+        fields.push(("category".to_string(), category.bits().into()));
+    }
+    if mask != Layers::ALL {
+        fields.push(("mask".to_string(), mask.bits().into()));
+    }
+}
+
+fn vec2_field(json: &Json, key: &str, what: &str) -> Result<Vec2, SaveError> {
+    match field(json, key, what)?.as_array() {
+        Some([x, y]) => x
+            .as_f32()
+            .zip(y.as_f32())
+            .map(|(x, y)| Vec2::new(x, y))
+            .ok_or_else(|| invalid(format!("{} \"{}\" is not a number pair", what, key))),
+        _ => Err(invalid(format!(
+            "{} \"{}\" is not a [x, y] pair",
+            what, key
+        ))),
+    }
+}
+
+fn node_json(n: &Node) -> Json {
+    let mut fields = vec![("p".to_string(), vec2_json(n.p))];
+    if n.v.x != 0.0 || n.v.y != 0.0 {
+        fields.push(("v".to_string(), vec2_json(n.v)));
+    }
+    if n.fixed_x() {
+        fields.push(("fixed_x".to_string(), n.fixed_p.x.into()));
+    }
+    if n.fixed_y() {
+        fields.push(("fixed_y".to_string(), n.fixed_p.y.into()));
+    }
+    if let Some(motor) = n.motor {
+        fields.push((
+            "motor".to_string(),
+            Json::Object(vec![
+                ("speed".to_string(), motor.speed.into()),
+                ("max_torque".to_string(), motor.max_torque.into()),
+                ("enabled".to_string(), Json::Bool(motor.enabled)),
+            ]),
+        ));
+    }
+    if n.mass != 1.0 {
+        fields.push(("mass".to_string(), n.mass.into()));
+    }
+    push_layers(&mut fields, n.category, n.mask);
+    if n.restitution != 1.0 {
+        fields.push(("restitution".to_string(), n.restitution.into()));
+    }
+    if n.friction != 0.0 {
+        fields.push(("friction".to_string(), n.friction.into()));
+    }
+    Json::Object(fields)
+}
+
+fn json_node(json: &Json, what: &str) -> Result<Node, SaveError> {
+    let mut node = Node {
+        p: vec2_field(json, "p", what)?,
+        ..Default::default()
+    };
+    if json.get("v").is_some() {
+        node.v = vec2_field(json, "v", what)?;
+    }
+    if let Some(x) = opt_f32_field(json, "fixed_x", what)? {
+        node.fixed_p.x = x;
+    }
+    if let Some(y) = opt_f32_field(json, "fixed_y", what)? {
+        node.fixed_p.y = y;
+    }
+    if let Some(motor) = json.get("motor") {
+        let what = format!("{} motor", what);
+        let max_torque = f32_field(motor, "max_torque", &what)?;
+        if max_torque.is_nan() || max_torque <= 0.0 {
+            return Err(invalid(format!("{} \"max_torque\" must be positive", what)));
+        }
+        node.motor = Some(Motor {
+            speed: f32_field(motor, "speed", &what)?,
+            max_torque,
+            enabled: opt_bool_field(motor, "enabled", &what)?.unwrap_or(true),
+        });
+    } else if let Some(rotor_speed) = opt_f32_field(json, "rotor_speed", what)? {
+        node.motor = legacy_motor(rotor_speed);
+    }
+    node.mass = opt_f32_field(json, "mass", what)?.unwrap_or(1.0);
+    if node.mass.is_nan() || node.mass <= 0.0 {
+        return Err(invalid(format!("{} \"mass\" must be positive", what)));
+    }
+    node.category = layers_field(json, "category", what, Layers::DEFAULT)?;
+    node.mask = layers_field(json, "mask", what, Layers::ALL)?;
+    node.restitution = opt_f32_field(json, "restitution", what)?.unwrap_or(1.0);
+    node.friction = opt_f32_field(json, "friction", what)?.unwrap_or(0.0);
+    Ok(node)
+}
+
+fn link_json(l: &Link, n1: u32, n2: u32) -> Json {
+    let kind = match l {
+        Link::Link { .. } => "link",
+        Link::Rope { .. } => "rope",
+        Link::Hydraulic { .. } => "hydraulic",
+        Link::Spring { .. } => "spring",
+    };
+    let mut fields = vec![
+        ("type".to_string(), kind.into()),
+        ("n1".to_string(), n1.into()),
+        ("n2".to_string(), n2.into()),
+        ("dist".to_string(), l.dist().into()),
+    ];
+    match *l {
+        Link::Hydraulic {
+            speed,
+            min,
+            max,
+            control,
+            ..
+        } => {
+            fields.push(("speed".to_string(), speed.into()));
+            // A missing min means 0, so an unbounded one is written as null
+            let min = if min.is_finite() {
+                min.into()
+            } else {
+                Json::Null
+            };
+            fields.push(("min".to_string(), min));
+            if max.is_finite() {
+                fields.push(("max".to_string(), max.into()));
+            }
+            let control = match control {
+                HydraulicControl::Hold => "hold",
+                HydraulicControl::Extend => "extend",
+                HydraulicControl::Retract => "retract",
+                HydraulicControl::Pattern {
+                    wave,
+                    period,
+                    phase,
+                } => {
+                    fields.push(("period".to_string(), period.into()));
+                    fields.push(("phase".to_string(), phase.into()));
+                    match wave {
+                        Wave::Sine => "sine",
+                        Wave::Square => "square",
+                        Wave::PingPong => "ping_pong",
+                    }
+                }
+            };
+            fields.push(("control".to_string(), control.into()));
+        }
+        Link::Spring {
+            stiffness, damping, ..
+        } => {
+            fields.push(("stiffness".to_string(), stiffness.into()));
+            fields.push(("damping".to_string(), damping.into()));
+        }
+        Link::Link { .. } | Link::Rope { .. } => {}
+    }
+    if l.breakable() {
+        fields.push(("strength".to_string(), l.strength().into()));
+    }
+    Json::Object(fields)
+}
+
+fn json_control(json: &Json, what: &str) -> Result<HydraulicControl, SaveError> {
+    let Some(control) = json.get("control") else {
+        return Ok(HydraulicControl::Extend);
+    };
+    let wave = match control.as_str() {
+        Some("hold") => return Ok(HydraulicControl::Hold),
+        Some("extend") => return Ok(HydraulicControl::Extend),
+        Some("retract") => return Ok(HydraulicControl::Retract),
+        Some("sine") => Wave::Sine,
+        Some("square") => Wave::Square,
+        Some("ping_pong") => Wave::PingPong,
+        _ => return Err(invalid(format!("{} has unknown \"control\"", what))),
+    };
+    Ok(HydraulicControl::Pattern {
+        wave,
+        period: f32_field(json, "period", what)?,
+        phase: opt_f32_field(json, "phase", what)?.unwrap_or(0.0),
+    })
+}
+
+fn solver_json(solver: Solver) -> Json {
+    let Solver::Xpbd(xpbd) = solver else {
+        return Json::Object(vec![("type".to_string(), "impulse".into())]);
+    };
+    let order = match xpbd.order {
+        SolverOrder::GaussSeidel => "gauss_seidel",
+        SolverOrder::Jacobi => "jacobi",
+    };
+    Json::Object(vec![
+        ("type".to_string(), "xpbd".into()),
+        ("iterations".to_string(), xpbd.iterations.into()),
+        ("order".to_string(), order.into()),
+        ("link_compliance".to_string(), xpbd.link_compliance.into()),
+        ("rope_compliance".to_string(), xpbd.rope_compliance.into()),
+        (
+            "hydraulic_compliance".to_string(),
+            xpbd.hydraulic_compliance.into(),
+        ),
+    ])
+}
+
+fn json_solver(json: &Json) -> Result<Solver, SaveError> {
+    let what = "Scene \"solver\"";
+    match field(json, "type", what)?.as_str() {
+        Some("impulse") => Ok(Solver::Impulse),
+        Some("xpbd") => {
+            let defaults = Xpbd::default();
+            let iterations = match json.get("iterations") {
+                Some(n) => n
+                    .as_u32()
+                    .ok_or_else(|| invalid(format!("{} \"iterations\" is not a count", what)))?,
+                None => defaults.iterations,
+            };
+            let order = match json.get("order").map(|o| o.as_str()) {
+                None => defaults.order,
+                Some(Some("gauss_seidel")) => SolverOrder::GaussSeidel,
+                Some(Some("jacobi")) => SolverOrder::Jacobi,
+                Some(_) => return Err(invalid(format!("{} has unknown \"order\"", what))),
+            };
+            let compliance = |key, default: f32| -> Result<f32, SaveError> {
+                Ok(opt_f32_field(json, key, what)?.unwrap_or(default))
+            };
+            Ok(Solver::Xpbd(Xpbd {
+                iterations,
+                order,
+                link_compliance: compliance("link_compliance", defaults.link_compliance)?,
+                rope_compliance: compliance("rope_compliance", defaults.rope_compliance)?,
+                hydraulic_compliance: compliance(
+                    "hydraulic_compliance",
+                    defaults.hydraulic_compliance,
+                )?,
+            }))
+        }
+        _ => Err(invalid(format!("{} has unknown \"type\"", what))),
+    }
+}
+
+fn adaptive_json(adaptive: AdaptiveSubsteps) -> Json {
+    Json::Object(vec![
+        ("max_substeps".to_string(), adaptive.max_substeps.into()),
+        ("max_travel".to_string(), adaptive.max_travel.into()),
+        ("max_link_error".to_string(), adaptive.max_link_error.into()),
+        (
+            "max_energy_drift".to_string(),
+            adaptive.max_energy_drift.into(),
+        ),
+    ])
+}
+
+fn json_adaptive(json: &Json) -> Result<AdaptiveSubsteps, SaveError> {
+    let what = "Scene \"adaptive\"";
+    let defaults = AdaptiveSubsteps::default();
+    let max_substeps = match json.get("max_substeps") {
+        Some(n) => n
+            .as_u32()
+            .ok_or_else(|| invalid(format!("{} \"max_substeps\" is not a count", what)))?,
+        None => defaults.max_substeps,
+    };
+    let tolerance = |key, default: f32| -> Result<f32, SaveError> {
+        Ok(opt_f32_field(json, key, what)?.unwrap_or(default))
+    };
+    Ok(AdaptiveSubsteps {
+        max_substeps,
+        max_travel: tolerance("max_travel", defaults.max_travel)?,
+        max_link_error: tolerance("max_link_error", defaults.max_link_error)?,
+        max_energy_drift: tolerance("max_energy_drift", defaults.max_energy_drift)?,
+    })
+}
+
+fn terrain_json(t: &Terrain) -> Json {
+    let (kind, points) = match &t.shape {
+        Shape::Segment { a, b } => ("segment", vec![*a, *b]),
+        Shape::Polyline { points } => ("polyline", points.clone()),
+        Shape::Polygon { points } => ("polygon", points.clone()),
+    };
+    let mut fields = vec![
+        ("type".to_string(), kind.into()),
+        (
+            "points".to_string(),
+            Json::Array(points.into_iter().map(vec2_json).collect()),
+        ),
+        ("restitution".to_string(), t.restitution.into()),
+        ("friction".to_string(), t.friction.into()),
+    ];
+    push_layers(&mut fields, t.category, t.mask);
+    Json::Object(fields)
+}
+
+fn json_terrain(json: &Json, what: &str) -> Result<Terrain, SaveError> {
+    let points = field(json, "points", what)?
+        .as_array()
+        .ok_or_else(|| invalid(format!("{} \"points\" is not an array", what)))?
+        .iter()
+        .map(|p| match p.as_array() {
+            Some([x, y]) => x.as_f32().zip(y.as_f32()).map(|(x, y)| Vec2::new(x, y)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid(format!("{} \"points\" are not [x, y] pairs", what)))?;
+    let shape = match (field(json, "type", what)?.as_str(), points.as_slice()) {
+        (Some("segment"), &[a, b]) => Shape::Segment { a, b },
+        (Some("segment"), _) => return Err(invalid(format!("{} needs exactly 2 points", what))),
+        (Some("polyline"), _) => Shape::Polyline { points },
+        (Some("polygon"), _) => Shape::Polygon { points },
+        _ => return Err(invalid(format!("{} has unknown \"type\"", what))),
+    };
+    let defaults = Terrain::default();
+    Ok(Terrain {
+        shape,
+        restitution: opt_f32_field(json, "restitution", what)?.unwrap_or(defaults.restitution),
+        friction: opt_f32_field(json, "friction", what)?.unwrap_or(defaults.friction),
+        category: layers_field(json, "category", what, defaults.category)?,
+        mask: layers_field(json, "mask", what, defaults.mask)?,
+    })
+}
+
+fn field_json(f: &ForceField) -> Json {
+    let kind = match f {
+        ForceField::Uniform { .. } => "uniform",
+        ForceField::Attractor { .. } => "attractor",
+        ForceField::Vortex { .. } => "vortex",
+    };
+    let mut fields = vec![
+        ("type".to_string(), kind.into()),
+        ("center".to_string(), vec2_json(f.center())),
+    ];
+    if f.radius() != ForceField::UNBOUNDED {
+        fields.push(("radius".to_string(), f.radius().into()));
+    }
+    match *f {
+        ForceField::Uniform { force, .. } => fields.push(("force".to_string(), vec2_json(force))),
+        ForceField::Attractor { strength, .. } | ForceField::Vortex { strength, .. } => {
+            fields.push(("strength".to_string(), strength.into()))
+        }
+    }
+    Json::Object(fields)
+}
+
+fn json_field(json: &Json, what: &str) -> Result<ForceField, SaveError> {
+    let center = vec2_field(json, "center", what)?;
+    let radius = opt_f32_field(json, "radius", what)?.unwrap_or(ForceField::UNBOUNDED);
+    match field(json, "type", what)?.as_str() {
+        Some("uniform") => Ok(ForceField::Uniform {
+            center,
+            radius,
+            force: vec2_field(json, "force", what)?,
+        }),
+        Some("attractor") => Ok(ForceField::Attractor {
+            center,
+            radius,
+            strength: f32_field(json, "strength", what)?,
+        }),
+        Some("vortex") => Ok(ForceField::Vortex {
+            center,
+            radius,
+            strength: f32_field(json, "strength", what)?,
+        }),
+        _ => Err(invalid(format!("{} has unknown \"type\"", what))),
+    }
+}
+
+fn constraint_json(c: &Constraint, node_indices: &HashMap<NodeId, u32>) -> Json {
+    let mut fields = match *c {
+        Constraint::Track { origin, dir, .. } => vec![
+            ("type".to_string(), "track".into()),
+            ("node".to_string(), node_indices[&c.node()].into()),
+            ("origin".to_string(), vec2_json(origin)),
+            ("dir".to_string(), vec2_json(dir)),
+        ],
+        Constraint::Slider { a, b, .. } => vec![
+            ("type".to_string(), "slider".into()),
+            ("node".to_string(), node_indices[&c.node()].into()),
+            ("a".to_string(), node_indices[&a].into()),
+            ("b".to_string(), node_indices[&b].into()),
+        ],
+        Constraint::Angle {
+            a,
+            b,
+            angle,
+            stiffness,
+            ..
+        } => {
+            let mut fields = vec![
+                ("type".to_string(), "angle".into()),
+                ("node".to_string(), node_indices[&c.node()].into()),
+                ("a".to_string(), node_indices[&a].into()),
+                ("b".to_string(), node_indices[&b].into()),
+                ("angle".to_string(), angle.into()),
+            ];
+            if stiffness != Constraint::RIGID {
+                fields.push(("stiffness".to_string(), stiffness.into()));
+            }
+            fields
+        }
+        Constraint::Pulley {
+            a,
+            b,
+            length,
+            ratio,
+            ..
+        } => vec![
+            ("type".to_string(), "pulley".into()),
+            ("node".to_string(), node_indices[&c.node()].into()),
+            ("a".to_string(), node_indices[&a].into()),
+            ("b".to_string(), node_indices[&b].into()),
+            ("length".to_string(), length.into()),
+            ("ratio".to_string(), ratio.into()),
+        ],
+        Constraint::Gear { other, ratio, .. } => vec![
+            ("type".to_string(), "gear".into()),
+            ("node".to_string(), node_indices[&c.node()].into()),
+            ("other".to_string(), node_indices[&other].into()),
+            ("ratio".to_string(), ratio.into()),
+        ],
+    };
+    let (min, max) = c.limits();
+    if min.is_finite() {
+        fields.push(("min".to_string(), min.into()));
+    }
+    if max.is_finite() {
+        fields.push(("max".to_string(), max.into()));
+    }
+    Json::Object(fields)
+}
+
+fn json_constraint(json: &Json, i: u32, node_ids: &[NodeId]) -> Result<Constraint, SaveError> {
+    let what = format!("Constraint {}", i);
+    let node_id = |key| {
+        let node = field(json, key, &what)?
+            .as_u32()
+            .ok_or_else(|| invalid(format!("{} \"{}\" is not an index", what, key)))?;
+        node_ids
+            .get(node as usize)
+            .copied()
+            .ok_or(SaveError::ConstraintNodeOutOfRange {
+                constraint: i,
+                node,
+                nodes: node_ids.len() as u32,
+            })
+    };
+    let node = node_id("node")?;
+    let min = opt_f32_field(json, "min", &what)?.unwrap_or(Constraint::UNLIMITED.0);
+    let max = opt_f32_field(json, "max", &what)?.unwrap_or(Constraint::UNLIMITED.1);
+    if !valid_limits(min, max) {
+        return Err(SaveError::BadConstraintLimits {
+            constraint: i,
+            min,
+            max,
+        });
+    }
+    match field(json, "type", &what)?.as_str() {
+        Some("track") => {
+            let dir = vec2_field(json, "dir", &what)?;
+            if !valid_dir(dir) {
+                return Err(SaveError::BadTrackDir { constraint: i, dir });
+            }
+            Ok(Constraint::Track {
+                node,
+                origin: vec2_field(json, "origin", &what)?,
+                dir: dir.norm(),
+                min,
+                max,
+            })
+        }
+        Some("slider") => Ok(Constraint::Slider {
+            node,
+            a: node_id("a")?,
+            b: node_id("b")?,
+            min,
+            max,
+        }),
+        Some("angle") => Ok(Constraint::Angle {
+            node,
+            a: node_id("a")?,
+            b: node_id("b")?,
+            angle: f32_field(json, "angle", &what)?,
+            stiffness: opt_f32_field(json, "stiffness", &what)?.unwrap_or(Constraint::RIGID),
+            min,
+            max,
+        }),
+        Some("pulley") => Ok(Constraint::Pulley {
+            node,
+            a: node_id("a")?,
+            b: node_id("b")?,
+            length: f32_field(json, "length", &what)?,
+            ratio: opt_f32_field(json, "ratio", &what)?.unwrap_or(1.0),
+        }),
+        Some("gear") => Ok(Constraint::Gear {
+            node,
+            other: node_id("other")?,
+            ratio: f32_field(json, "ratio", &what)?,
+        }),
+        _ => Err(invalid(format!("{} has unknown \"type\"", what))),
+    }
+}
+
+impl World {
+    pub fn to_json(&self) -> Json {
+        let mut node_indices = HashMap::new();
+        let mut nodes = Vec::new();
+        for (i, (id, n)) in self.nodes.iter().enumerate() {
+            node_indices.insert(id, i as u32);
+            nodes.push(node_json(n));
+        }
+        let links = self
+            .links
+            .values()
+            .map(|l| link_json(l, node_indices[&l.n1()], node_indices[&l.n2()]))
+            .collect();
+
+        let mut fields = vec![
+            ("version".to_string(), SAVE_VERSION.into()),
+            ("radius".to_string(), self.radius.into()),
+            (
+                "link_collision".to_string(),
+                Json::Bool(self.link_collision),
+            ),
+            (
+                "skip_linked_collisions".to_string(),
+                Json::Bool(self.skip_linked_collisions),
+            ),
+            ("deterministic".to_string(), Json::Bool(self.deterministic)),
+            ("gravity".to_string(), vec2_json(self.gravity)),
+            ("linear_drag".to_string(), self.linear_drag.into()),
+            ("quadratic_drag".to_string(), self.quadratic_drag.into()),
+            ("time".to_string(), self.time.into()),
+            ("solver".to_string(), solver_json(self.solver)),
+            ("integrator".to_string(), self.integrator.name().into()),
+            ("nodes".to_string(), Json::Array(nodes)),
+            ("links".to_string(), Json::Array(links)),
+            (
+                "terrain".to_string(),
+                Json::Array(self.terrain.values().map(terrain_json).collect()),
+            ),
+            (
+                "fields".to_string(),
+                Json::Array(self.fields.values().map(field_json).collect()),
+            ),
+            (
+                "constraints".to_string(),
+                Json::Array(
+                    self.constraints
+                        .values()
+                        .map(|c| constraint_json(c, &node_indices))
+                        .collect(),
+                ),
+            ),
+            (
+                "chains".to_string(),
+                Json::Array(
+                    self.chains
+                        .values()
+                        .map(|c| {
+                            Json::Array(c.nodes.iter().map(|n| node_indices[n].into()).collect())
+                        })
+                        .collect(),
+                ),
+            ),
+        ];
+        // Ticks keep their fixed substeps unless the scene says otherwise
+        if let Some(adaptive) = self.adaptive {
+            let at = fields.iter().position(|(k, _)| k == "integrator").unwrap() + 1;
+            fields.insert(at, ("adaptive".to_string(), adaptive_json(adaptive)));
+        }
+        Json::Object(fields)
+    }
+
+    pub fn from_json(json: &Json) -> Result<Self, SaveError> {
+        let version = json
+            .get("version")
+            .and_then(|v| v.as_u32())
+            .unwrap_or(SAVE_VERSION);
+        if version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let stiffness_scale = if version < 9 {
+            LEGACY_SPRING_STIFFNESS
+        } else {
+            1.0
+        };
+
+        let defaults = Self::default();
+        let mut world = Self {
+            radius: f32_field(json, "radius", "Scene")?,
+            gravity: match json.get("gravity") {
+                Some(_) => vec2_field(json, "gravity", "Scene")?,
+                None => defaults.gravity,
+            },
+            linear_drag: opt_f32_field(json, "linear_drag", "Scene")?
+                .unwrap_or(defaults.linear_drag),
+            quadratic_drag: opt_f32_field(json, "quadratic_drag", "Scene")?
+                .unwrap_or(defaults.quadratic_drag),
+            time: opt_f32_field(json, "time", "Scene")?.unwrap_or(0.0),
+            solver: match json.get("solver") {
+                Some(solver) => json_solver(solver)?,
+                None => Solver::Impulse,
+            },
+            integrator: match json.get("integrator") {
+                Some(name) => name
+                    .as_str()
+                    .and_then(IntegratorKind::from_name)
+                    .ok_or_else(|| invalid("Scene has unknown \"integrator\""))?,
+                None => IntegratorKind::Euler,
+            },
+            adaptive: json.get("adaptive").map(json_adaptive).transpose()?,
+            link_collision: opt_bool_field(json, "link_collision", "Scene")?.unwrap_or(false),
+            skip_linked_collisions: opt_bool_field(json, "skip_linked_collisions", "Scene")?
+                .unwrap_or(false),
+            deterministic: opt_bool_field(json, "deterministic", "Scene")?.unwrap_or(false),
+            ..defaults
+        };
+
+        let nodes = field(json, "nodes", "Scene")?
+            .as_array()
+            .ok_or_else(|| invalid("Scene \"nodes\" is not an array"))?;
+        let mut node_ids = Vec::with_capacity(nodes.len());
+        for (i, n) in nodes.iter().enumerate() {
+            node_ids.push(world.add(json_node(n, &format!("Node {}", i))?));
+        }
+
+        let links = field(json, "links", "Scene")?
+            .as_array()
+            .ok_or_else(|| invalid("Scene \"links\" is not an array"))?;
+        for (i, l) in links.iter().enumerate() {
+            let what = format!("Link {}", i);
+            let node_id = |key| {
+                let node = field(l, key, &what)?
+                    .as_u32()
+                    .ok_or_else(|| invalid(format!("{} \"{}\" is not an index", what, key)))?;
+                node_ids
+                    .get(node as usize)
+                    .copied()
+                    .ok_or(SaveError::NodeOutOfRange {
+                        link: i as u32,
+                        node,
+                        nodes: node_ids.len() as u32,
+                    })
+            };
+            let (n1, n2) = (node_id("n1")?, node_id("n2")?);
+            let dist = f32_field(l, "dist", &what)?;
+            let strength = opt_f32_field(l, "strength", &what)?.unwrap_or(Link::UNBREAKABLE);
+            let link = match field(l, "type", &what)?.as_str() {
+                Some("link") => Link::Link {
+                    n1,
+                    n2,
+                    dist,
+                    strength,
+                },
+                Some("rope") => Link::Rope {
+                    n1,
+                    n2,
+                    dist,
+                    strength,
+                },
+                Some("hydraulic") => {
+                    let min = match l.get("min") {
+                        Some(Json::Null) => f32::NEG_INFINITY,
+                        _ => opt_f32_field(l, "min", &what)?.unwrap_or(0.0),
+                    };
+                    let max = opt_f32_field(l, "max", &what)?.unwrap_or(f32::INFINITY);
+                    if !valid_limits(min, max) {
+                        return Err(SaveError::BadLinkLimits {
+                            link: i as u32,
+                            min,
+                            max,
+                        });
+                    }
+                    Link::Hydraulic {
+                        n1,
+                        n2,
+                        dist,
+                        speed: f32_field(l, "speed", &what)?,
+                        strength,
+                        min,
+                        max,
+                        control: json_control(l, &what)?,
+                    }
+                }
+                Some("spring") => Link::Spring {
+                    n1,
+                    n2,
+                    dist,
+                    stiffness: f32_field(l, "stiffness", &what)? * stiffness_scale,
+                    damping: opt_f32_field(l, "damping", &what)?.unwrap_or(0.0),
+                    strength,
+                },
+                _ => return Err(invalid(format!("{} has unknown \"type\"", what))),
+            };
+            world
+                .link_node(link)
+                .ok_or(SaveError::InvalidLink(i as u32))?;
+        }
+
+        if let Some(terrain) = json.get("terrain") {
+            let terrain = terrain
+                .as_array()
+                .ok_or_else(|| invalid("Scene \"terrain\" is not an array"))?;
+            for (i, t) in terrain.iter().enumerate() {
+                world.add_terrain(json_terrain(t, &format!("Terrain {}", i))?);
+            }
+        }
+
+        if let Some(fields) = json.get("fields") {
+            let fields = fields
+                .as_array()
+                .ok_or_else(|| invalid("Scene \"fields\" is not an array"))?;
+            for (i, f) in fields.iter().enumerate() {
+                world.add_field(json_field(f, &format!("Field {}", i))?);
+            }
+        }
+
+        if let Some(constraints) = json.get("constraints") {
+            let constraints = constraints
+                .as_array()
+                .ok_or_else(|| invalid("Scene \"constraints\" is not an array"))?;
+            for (i, c) in constraints.iter().enumerate() {
+                world.add_constraint(json_constraint(c, i as u32, &node_ids)?);
+            }
+        }
+
+        if let Some(chains) = json.get("chains") {
+            let chains = chains
+                .as_array()
+                .ok_or_else(|| invalid("Scene \"chains\" is not an array"))?;
+            for (i, c) in chains.iter().enumerate() {
+                let nodes = c
+                    .as_array()
+                    .and_then(|c| {
+                        c.iter()
+                            .map(|n| node_ids.get(n.as_u32()? as usize).copied())
+                            .collect::<Option<Vec<NodeId>>>()
+                    })
+                    .ok_or(SaveError::BadChain(i as u32))?;
+                world
+                    .adopt_chain(nodes)
+                    .ok_or(SaveError::BadChain(i as u32))?;
+            }
+        }
+
+        Ok(world)
+    }
+}
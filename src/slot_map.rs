@@ -0,0 +1,284 @@
+use std::{
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+/// Generational handle into a `SlotMap<T>`, stays valid across removals of other elements
+pub struct Handle<T> {
+    idx: u32,
+    gen: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    /// Handle that never refers to an element
+    pub const NULL: Self = Self::new(u32::MAX, 0);
+
+    const fn new(idx: u32, gen: u32) -> Self {
+        Self {
+            idx,
+            gen,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn idx(&self) -> u32 {
+        self.idx
+    }
+
+    pub fn gen(&self) -> u32 {
+        self.gen
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.idx == u32::MAX
+    }
+}
+
+impl<T> Default for Handle<T> {
+    fn default() -> Self {
+        Self::NULL
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx && self.gen == other.gen
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+        self.gen.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({}v{})", self.idx, self.gen)
+    }
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    gen: u32,
+    value: Option<T>,
+}
+
+/// Vec backed storage with O(1) insert/remove that hands out generational handles,
+/// a removed element's handle never aliases a later insertion
+#[derive(Clone)]
+pub struct SlotMap<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Default for SlotMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SlotMap<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        self.len += 1;
+        if let Some(idx) = self.free.pop() {
+            let slot = &mut self.slots[idx as usize];
+            slot.value = Some(value);
+            Handle::new(idx, slot.gen)
+        } else {
+            self.slots.push(Slot {
+                gen: 0,
+                value: Some(value),
+            });
+            Handle::new(self.slots.len() as u32 - 1, 0)
+        }
+    }
+
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let slot = self.slots.get_mut(handle.idx as usize)?;
+        if slot.gen != handle.gen || slot.value.is_none() {
+            return None;
+        }
+        slot.gen = slot.gen.wrapping_add(1);
+        self.free.push(handle.idx);
+        self.len -= 1;
+        slot.value.take()
+    }
+
+    pub fn clear(&mut self) {
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            if slot.value.take().is_some() {
+                slot.gen = slot.gen.wrapping_add(1);
+                self.free.push(i as u32);
+            }
+        }
+        self.len = 0;
+    }
+
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        let slot = self.slots.get(handle.idx as usize)?;
+        if slot.gen != handle.gen {
+            return None;
+        }
+        slot.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        let slot = self.slots.get_mut(handle.idx as usize)?;
+        if slot.gen != handle.gen {
+            return None;
+        }
+        slot.value.as_mut()
+    }
+
+    /// Mutable access to two distinct elements at once
+    pub fn get2_mut(&mut self, a: Handle<T>, b: Handle<T>) -> Option<(&mut T, &mut T)> {
+        if a.idx == b.idx || !self.contains(a) || !self.contains(b) {
+            return None;
+        }
+        let (lo, hi) = (a.idx.min(b.idx) as usize, a.idx.max(b.idx) as usize);
+        let (left, right) = self.slots.split_at_mut(hi);
+        let lo = left[lo].value.as_mut()?;
+        let hi = right[0].value.as_mut()?;
+        if a.idx < b.idx {
+            Some((lo, hi))
+        } else {
+            Some((hi, lo))
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            slot.value
+                .as_ref()
+                .map(|v| (Handle::new(i as u32, slot.gen), v))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T>, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let gen = slot.gen;
+            slot.value.as_mut().map(|v| (Handle::new(i as u32, gen), v))
+        })
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = Handle<T>> + '_ {
+        self.iter().map(|(h, _)| h)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+}
+
+impl<T> Index<Handle<T>> for SlotMap<T> {
+    type Output = T;
+    fn index(&self, handle: Handle<T>) -> &T {
+        self.get(handle)
+            .unwrap_or_else(|| panic!("Invalid handle {:?}", handle))
+    }
+}
+
+impl<T> IndexMut<Handle<T>> for SlotMap<T> {
+    fn index_mut(&mut self, handle: Handle<T>) -> &mut T {
+        self.get_mut(handle)
+            .unwrap_or_else(|| panic!("Invalid handle {:?}", handle))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut map = SlotMap::new();
+        let a = map.insert("a");
+        assert_eq!(map.remove(a), Some("a"));
+        let b = map.insert("b");
+        assert_eq!(b.idx(), a.idx());
+        assert_eq!(b.gen(), a.gen() + 1);
+        assert_ne!(a, b);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn stale_handles_find_nothing() {
+        let mut map = SlotMap::new();
+        let a = map.insert(1);
+        map.remove(a);
+        assert_eq!(map.get(a), None);
+        assert_eq!(map.get_mut(a), None);
+        assert_eq!(map.remove(a), None);
+
+        // Still stale once the slot holds something else
+        let b = map.insert(2);
+        assert_eq!(map.get(a), None);
+        assert!(!map.contains(a));
+        assert_eq!(map.remove(a), None);
+        assert_eq!(map[b], 2);
+        assert_eq!(map.get(Handle::NULL), None);
+
+        map.clear();
+        assert_eq!(map.get(b), None);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn get2_mut_needs_two_live_elements() {
+        let mut map = SlotMap::new();
+        let a = map.insert(1);
+        let b = map.insert(2);
+        assert!(map.get2_mut(a, a).is_none());
+
+        let (x, y) = map.get2_mut(b, a).unwrap();
+        (*x, *y) = (*x * 10, *y * 10);
+        assert_eq!((map[a], map[b]), (10, 20));
+
+        map.remove(b);
+        let c = map.insert(3);
+        assert!(map.get2_mut(a, b).is_none());
+        assert!(map.get2_mut(b, a).is_none());
+        // A stale handle to the same slot as a live one
+        assert!(map.get2_mut(b, c).is_none());
+        assert!(map.get2_mut(a, c).is_some());
+    }
+}
@@ -0,0 +1,67 @@
+/// Order position based corrections are applied in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SolverOrder {
+    /// Every link moves its nodes right away, the next one sees the result, converges fastest
+    GaussSeidel,
+    /// Every link reads the same positions and a node moves by the mean of its corrections,
+    /// independent of link order
+    Jacobi,
+}
+
+/// Extended position based dynamics settings, compliance is the inverse of stiffness
+/// in units of stretch per force, zero for perfectly rigid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Xpbd {
+    pub iterations: u32,
+    pub order: SolverOrder,
+    pub link_compliance: f32,
+    pub rope_compliance: f32,
+    pub hydraulic_compliance: f32,
+}
+
+impl Default for Xpbd {
+    fn default() -> Self {
+        Self {
+            iterations: 8,
+            order: SolverOrder::GaussSeidel,
+            link_compliance: 0.0,
+            rope_compliance: 1e-4,
+            hydraulic_compliance: 0.0,
+        }
+    }
+}
+
+/// How `World::step` keeps links at their length
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Solver {
+    /// Position pushes plus velocity kicks, links get softer with longer steps
+    #[default]
+    Impulse,
+    /// Solves links on predicted positions, their stiffness stays the same whatever the step,
+    /// springs use the inverse of their own stiffness as compliance
+    Xpbd(Xpbd),
+}
+
+/// Tolerances adaptive substepping holds ticks to, a tick gets as many substeps as the strictest
+/// one needs, up to `max_substeps`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSubsteps {
+    pub max_substeps: u32,
+    /// Distance the fastest node may travel per substep as a fraction of the node radius
+    pub max_travel: f32,
+    /// Largest stretch or compression of a rigid link or taut rope relative to its length
+    pub max_link_error: f32,
+    /// Largest mechanical energy gain over a tick relative to the kinetic energy
+    pub max_energy_drift: f32,
+}
+
+impl Default for AdaptiveSubsteps {
+    fn default() -> Self {
+        Self {
+            max_substeps: 64,
+            max_travel: 0.5,
+            max_link_error: 0.01,
+            max_energy_drift: 0.05,
+        }
+    }
+}
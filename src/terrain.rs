@@ -0,0 +1,120 @@
+use crate::{Handle, Layers, Vec2};
+
+pub type TerrainId = Handle<Terrain>;
+
+/// Static collision geometry, polygons must be convex and may use either winding
+#[derive(Clone, Debug)]
+pub enum Shape {
+    Segment { a: Vec2, b: Vec2 },
+    Polyline { points: Vec<Vec2> },
+    Polygon { points: Vec<Vec2> },
+}
+
+/// Immovable shape nodes collide with
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    pub shape: Shape,
+    /// Fraction of the normal velocity kept after a bounce
+    pub restitution: f32,
+    /// Coulomb friction coefficient against sliding along the surface
+    pub friction: f32,
+    pub category: Layers,
+    pub mask: Layers,
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self {
+            shape: Shape::Segment {
+                a: Vec2::ZERO,
+                b: Vec2::ZERO,
+            },
+            restitution: 0.2,
+            friction: 0.5,
+            category: Layers::DEFAULT,
+            mask: Layers::ALL,
+        }
+    }
+}
+
+// Closest point to `p` on segment `ab`
+fn closest_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b - a;
+    let len2 = ab.len2();
+    if len2 == 0.0 {
+        return a;
+    }
+    a + ab * ((p - a).dot(&ab) / len2).clamp(0.0, 1.0)
+}
+
+impl Shape {
+    pub fn move_by(&mut self, x: f32, y: f32) {
+        match self {
+            Shape::Segment { a, b } => {
+                *a += Vec2::new(x, y);
+                *b += Vec2::new(x, y);
+            }
+            Shape::Polyline { points } | Shape::Polygon { points } => {
+                for p in points {
+                    *p += Vec2::new(x, y);
+                }
+            }
+        }
+    }
+
+    /// Segments making up the outline, polygons are closed
+    pub fn edges(&self) -> Vec<(Vec2, Vec2)> {
+        match self {
+            Shape::Segment { a, b } => vec![(*a, *b)],
+            Shape::Polyline { points } => points.windows(2).map(|w| (w[0], w[1])).collect(),
+            Shape::Polygon { points } => (0..points.len())
+                .map(|i| (points[i], points[(i + 1) % points.len()]))
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        let Shape::Polygon { points } = self else {
+            return false;
+        };
+        if points.len() < 3 {
+            return false;
+        }
+        let (mut pos, mut neg) = (false, false);
+        for (a, b) in self.edges() {
+            let side = (b - a).cross(&(p - a));
+            pos |= side > 0.0;
+            neg |= side < 0.0;
+        }
+        !(pos && neg)
+    }
+
+    /// Direction and depth to push a circle of radius `r` at `p` out of the shape
+    pub fn contact(&self, p: Vec2, r: f32) -> Option<(Vec2, f32)> {
+        let inside = self.contains(p);
+        let mut best: Option<(Vec2, f32)> = None;
+        for (a, b) in self.edges() {
+            let q = closest_on_segment(p, a, b);
+            let dist = p.dist(&q);
+            // Normal from the surface towards the node, flipped when the node sank inside a polygon
+            let (normal, depth) = if inside {
+                ((q - p) / dist, r + dist)
+            } else if dist < r {
+                ((p - q) / dist, r - dist)
+            } else {
+                continue;
+            };
+            let normal = if dist > 0.0 {
+                normal
+            } else {
+                (b - a).norm().rot90()
+            };
+            // Inside a polygon the nearest edge is the shortest way out, outside the deepest overlap wins
+            let better = best.is_none_or(|(_, d)| if inside { depth < d } else { depth > d });
+            if better {
+                best = Some((normal, depth));
+            }
+        }
+        best
+    }
+}
@@ -169,18 +169,18 @@ impl Funcs for f32 {
     }
 
     fn rand(self) -> f32 {
-        let mut x: u32 = unsafe { std::mem::transmute(self) };
+        let mut x: u32 = self.to_bits();
         x ^= x >> 16;
         x = x.wrapping_mul(0x7feb352d);
         x ^= x >> 15;
         x = x.wrapping_mul(0x846ca68b);
         x ^= x >> 16;
-        x as f32 / std::u32::MAX as f32
+        x as f32 / u32::MAX as f32
     }
 
     fn noise(self) -> f32 {
         let fl = self.floor();
-        return fl.rand().lerp((fl + 1.0).rand(), self.fract());
+        fl.rand().lerp((fl + 1.0).rand(), self.fract())
     }
 
     fn fbm(self, oct: u32) -> f32 {
@@ -195,7 +195,7 @@ impl Funcs for f32 {
             a *= 0.5;
             p *= 2.0;
         }
-        return s / m;
+        s / m
     }
 
     fn smooth(self) -> Self {
@@ -249,15 +249,15 @@ impl Vec2 {
     }
 
     pub fn norm(&self) -> Self {
-        self.clone() / self.len()
+        *self / self.len()
     }
 
     pub fn dist2(&self, rhs: &Self) -> f32 {
-        (rhs.clone() - *self).len2()
+        (*rhs - *self).len2()
     }
 
     pub fn dist(&self, rhs: &Self) -> f32 {
-        (rhs.clone() - *self).len()
+        (*rhs - *self).len()
     }
 
     pub fn sign(&self) -> Self {